@@ -0,0 +1,55 @@
+//! Size of the e-graph of `complex_ifs` after saturation, with and without distributing
+//! operators over phi nodes. Run with `cargo bench --bench phi_distribution`.
+
+use std::time::{Duration, Instant};
+
+use egg::StopReason;
+use lang::conv::to_epeg::parse_function;
+use lang::optimizer::{Limits, Optimizer, Phase};
+use lang::rules::RuleSelection;
+use lang::Rewrite;
+use llvm_ir::Module;
+
+const NODE_LIMIT: usize = 100_000;
+
+fn saturate(name: &str, rules: &[Rewrite]) {
+    let module = Module::from_bc_path("llvm_programs/complex_ifs/complex_ifs.bc").unwrap();
+    let function = parse_function(module.get_func_by_name("complex_ifs").unwrap());
+
+    let limits = Limits {
+        iters: 30,
+        nodes: NODE_LIMIT,
+        time: Duration::from_secs(60),
+    };
+    let start = Instant::now();
+    let saturated = Optimizer::default()
+        .with_phase(Phase::new(name, rules.to_vec()).with_limits(limits))
+        .run_function(&function);
+    let phase = &saturated.phases[0];
+    println!(
+        "{:<24} {:>3} iterations {:>7} nodes {:>6} classes {:>8.2?} {:?}",
+        name,
+        phase.iterations.len(),
+        saturated.egraph.total_number_of_nodes(),
+        saturated.egraph.number_of_classes(),
+        start.elapsed(),
+        phase.stop_reason,
+    );
+    assert!(
+        !matches!(phase.stop_reason, StopReason::NodeLimit(_)),
+        "{}: e-graph exceeded {} nodes",
+        name,
+        NODE_LIMIT
+    );
+}
+
+fn main() {
+    let rules = RuleSelection::default().select().unwrap();
+    let without = RuleSelection::default()
+        .exclude_group("distribution")
+        .select()
+        .unwrap();
+
+    saturate("without distribution", &without);
+    saturate("with distribution", &rules);
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use egg::{merge_option, Analysis, DidMerge, Id, Language, Symbol};
+
+use crate::bdd::{Atom, Bdd};
+
+use crate::lang::{Cond, Lang};
+use crate::EGraph;
+
+/// E-class analysis folding constants and tracking facts used by conditional rewrites. Boolean
+/// classes get a BDD over their atomic conditions, classes with equal BDDs are merged.
+#[derive(Debug, Clone, Default)]
+pub struct LangAnalysis {
+    /// Some class having each BDD seen so far
+    bdds: HashMap<Bdd, Id>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Constant {
+    I64(i64),
+    I1(bool),
+}
+
+impl Constant {
+    pub fn as_i64(self) -> Option<i64> {
+        match self {
+            Constant::I64(i) => Some(i),
+            Constant::I1(_) => None,
+        }
+    }
+
+    pub fn as_i1(self) -> Option<bool> {
+        match self {
+            Constant::I1(b) => Some(b),
+            Constant::I64(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Data {
+    pub constant: Option<Constant>,
+    /// The value is known to be an odd integer
+    pub odd: bool,
+    /// The value is known to be a non-zero integer
+    pub nonzero: bool,
+    /// The class is the initial witness of the allocation with this id
+    pub alloca: Option<u64>,
+    /// The class is a pointer into the allocation with this id
+    pub points_to: Option<u64>,
+    /// The value might depend on a loop iteration, that is on some theta node
+    pub variant: bool,
+    /// The class contains the variable with this name
+    pub var: Option<Symbol>,
+    /// Boolean function of the class over atomic conditions, if it is small enough
+    pub bdd: Option<Bdd>,
+}
+
+impl Analysis<Lang> for LangAnalysis {
+    type Data = Data;
+
+    fn make(egraph: &EGraph, enode: &Lang) -> Self::Data {
+        let data = |id: Id| &egraph[id].data;
+        let bdd = bdd(egraph, enode);
+        let constant = fold(enode, |id| data(id).constant)
+            .or_else(|| bdd.as_ref()?.as_constant().map(Constant::I1));
+        let odd = constant.and_then(Constant::as_i64).map_or_else(
+            || match enode {
+                Lang::Mul([l, r]) => data(*l).odd && data(*r).odd,
+                Lang::BOr([l, r]) => data(*l).odd || data(*r).odd,
+                Lang::Phi([_, t, e]) => data(*t).odd && data(*e).odd,
+                _ => false,
+            },
+            |i| i % 2 != 0,
+        );
+        let nonzero = constant.and_then(Constant::as_i64).map_or_else(
+            || match enode {
+                Lang::BOr([l, r]) => data(*l).nonzero || data(*r).nonzero,
+                Lang::Phi([_, t, e]) => data(*t).nonzero && data(*e).nonzero,
+                _ => odd,
+            },
+            |i| i != 0,
+        );
+        let alloca = match enode {
+            Lang::Alloca(id) => Some(*id),
+            _ => None,
+        };
+        let points_to = match enode {
+            Lang::Ptr(witness) => data(*witness).alloca,
+            _ => None,
+        };
+        let variant = matches!(enode, Lang::Theta(_))
+            || (constant.is_none() && enode.children().iter().any(|&id| data(id).variant));
+        let var = match enode {
+            Lang::Var(v) => Some(*v),
+            _ => None,
+        };
+        Data {
+            constant,
+            odd,
+            nonzero,
+            alloca,
+            points_to,
+            variant,
+            var,
+            bdd,
+        }
+    }
+
+    fn merge(&mut self, to: &mut Self::Data, from: Self::Data) -> DidMerge {
+        // An unsound rule can merge different constants, keep the first one so that the
+        // consistency checker gets a chance to report it
+        let constant = merge_option(&mut to.constant, from.constant, |a, b| {
+            DidMerge(false, *a != b)
+        });
+        let odd = merge_fact(&mut to.odd, from.odd);
+        let nonzero = merge_fact(&mut to.nonzero, from.nonzero);
+        let alloca = merge_option(&mut to.alloca, from.alloca, |a, b| DidMerge(false, *a != b));
+        let points_to = merge_option(&mut to.points_to, from.points_to, |a, b| {
+            DidMerge(false, *a != b)
+        });
+        // A class is only known to be invariant if none of its members is variant, which is
+        // conservative but keeps loop placeholders variant once they are unioned with a theta
+        let variant = merge_fact(&mut to.variant, from.variant);
+        let var = merge_option(&mut to.var, from.var, |a, b| DidMerge(false, *a != b));
+        let bdd = merge_option(&mut to.bdd, from.bdd, |a, b| DidMerge(false, *a != b));
+        constant | odd | nonzero | alloca | points_to | variant | var | bdd
+    }
+
+    fn modify(egraph: &mut EGraph, id: Id) {
+        if let Some(constant) = egraph[id].data.constant {
+            let node = match constant {
+                Constant::I64(i) => Lang::I64(i),
+                Constant::I1(b) => Lang::I1(b),
+            };
+            let added = egraph.add(node);
+            egraph.union_trusted(id, added, "constant folding");
+        }
+        share_bdd(egraph, id);
+        refresh_bdds(egraph, id);
+    }
+}
+
+/// Merges the class with the class already having the same BDD, if any
+fn share_bdd(egraph: &mut EGraph, id: Id) {
+    if let Some(bdd) = egraph[id].data.bdd.clone() {
+        match egraph.analysis.bdds.get(&bdd) {
+            Some(&other) => {
+                egraph.union_trusted(id, other, "equal BDDs");
+            }
+            None => {
+                egraph.analysis.bdds.insert(bdd, id);
+            }
+        }
+    }
+}
+
+/// Comparison atoms name the classes of their operands when they are made. Once classes are
+/// merged into `id`, the BDDs of the comparisons using them, and of the classes built on those,
+/// are computed again over the merged class.
+fn refresh_bdds(egraph: &mut EGraph, id: Id) {
+    let mut pending: Vec<Id> = egraph[id]
+        .parents()
+        .filter(|(node, _)| matches!(node, Lang::ICmp(..)))
+        .map(|(_, parent)| parent)
+        .collect();
+    while let Some(class) = pending.pop() {
+        let class = egraph.find(class);
+        let Some(old) = egraph[class].data.bdd.clone() else {
+            continue;
+        };
+        let new = egraph[class]
+            .nodes
+            .iter()
+            .find_map(|node| bdd(egraph, node));
+        let Some(new) = new.filter(|new| *new != old) else {
+            continue;
+        };
+        if let Some(b) = new.as_constant() {
+            let added = egraph.add(Lang::I1(b));
+            egraph.union_trusted(class, added, "constant folding");
+        }
+        egraph[class].data.bdd = Some(new);
+        pending.extend(egraph[class].parents().map(|(_, parent)| parent));
+        share_bdd(egraph, class);
+    }
+}
+
+/// Facts hold for every member of a class, so merging keeps the facts known on either side
+fn merge_fact(to: &mut bool, from: bool) -> DidMerge {
+    let merged = *to || from;
+    let did_merge = DidMerge(merged != *to, merged != from);
+    *to = merged;
+    did_merge
+}
+
+/// BDD of a class used as a boolean. Variables are only atoms where they are used as booleans,
+/// as they might be integers otherwise.
+pub fn boolean_bdd(egraph: &EGraph, id: Id) -> Option<Bdd> {
+    let data = &egraph[id].data;
+    data.bdd
+        .clone()
+        .or_else(|| data.var.map(|v| Bdd::atom(Atom::Var(v))))
+}
+
+fn bdd(egraph: &EGraph, enode: &Lang) -> Option<Bdd> {
+    let bdd = |id: &Id| egraph[*id].data.bdd.clone();
+    let boolean = |id: &Id| boolean_bdd(egraph, *id);
+    match enode {
+        Lang::I1(b) => Some(Bdd::constant(*b)),
+        Lang::And([l, r]) => boolean(l)?.and(&boolean(r)?),
+        Lang::Or([l, r]) => boolean(l)?.or(&boolean(r)?),
+        Lang::Not(op) => Some(boolean(op)?.not()),
+        Lang::Phi([c, t, e]) => {
+            // The arms are booleans if either of them is known to be one
+            let (t, e) = match (bdd(t), bdd(e)) {
+                (Some(t), Some(e)) => (t, e),
+                (Some(t), None) => (t, boolean(e)?),
+                (None, Some(e)) => (boolean(t)?, e),
+                (None, None) => return None,
+            };
+            Bdd::ite(&boolean(c)?, &t, &e)
+        }
+        Lang::ICmp(cond, [l, r]) => Some(Bdd::comparison(*cond, egraph.find(*l), egraph.find(*r))),
+        _ => None,
+    }
+}
+
+fn fold(enode: &Lang, constant: impl Fn(Id) -> Option<Constant>) -> Option<Constant> {
+    let i64_of = |id: &Id| constant(*id).and_then(Constant::as_i64);
+    let i1_of = |id: &Id| constant(*id).and_then(Constant::as_i1);
+    let int = |i: i64| Some(Constant::I64(i));
+    let boolean = |b: bool| Some(Constant::I1(b));
+    match enode {
+        Lang::I64(i) => int(*i),
+        Lang::I1(b) => boolean(*b),
+        Lang::Add([l, r]) => int(i64_of(l)?.wrapping_add(i64_of(r)?)),
+        Lang::Sub([l, r]) => int(i64_of(l)?.wrapping_sub(i64_of(r)?)),
+        Lang::Mul([l, r]) => int(i64_of(l)?.wrapping_mul(i64_of(r)?)),
+        Lang::Shl([l, r]) => match i64_of(r)? {
+            shift @ 0..=63 => int(i64_of(l)? << shift),
+            _ => None,
+        },
+        Lang::BAnd([l, r]) => int(i64_of(l)? & i64_of(r)?),
+        Lang::BOr([l, r]) => int(i64_of(l)? | i64_of(r)?),
+        Lang::BNot(op) => int(!i64_of(op)?),
+        Lang::And([l, r]) => boolean(i1_of(l)? && i1_of(r)?),
+        Lang::Or([l, r]) => boolean(i1_of(l)? || i1_of(r)?),
+        Lang::Not(op) => boolean(!i1_of(op)?),
+        Lang::ICmp(cond, [l, r]) => match (constant(*l)?, constant(*r)?) {
+            (Constant::I64(l), Constant::I64(r)) => boolean(match cond {
+                Cond::Eq => l == r,
+                Cond::Neq => l != r,
+                Cond::Lt => l < r,
+                Cond::Gt => l > r,
+                Cond::Leq => l <= r,
+                Cond::Geq => l >= r,
+            }),
+            (Constant::I1(l), Constant::I1(r)) => match cond {
+                Cond::Eq => boolean(l == r),
+                Cond::Neq => boolean(l != r),
+                _ => None,
+            },
+            _ => None,
+        },
+        Lang::Phi([c, t, e]) => match i1_of(c) {
+            Some(true) => constant(*t),
+            Some(false) => constant(*e),
+            None => constant(*t).filter(|t| constant(*e) == Some(*t)),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_folding() {
+        let mut egraph = EGraph::default();
+        let root = egraph.add_expr(&"(+ (* 2_i64 3_i64) (phi true 1_i64 x_v))".parse().unwrap());
+        egraph.rebuild();
+        assert_eq!(egraph[root].data.constant, Some(Constant::I64(7)));
+        let seven = egraph.lookup(Lang::I64(7)).unwrap();
+        assert_eq!(egraph.find(root), egraph.find(seven));
+    }
+
+    #[test]
+    fn facts() {
+        let mut egraph = EGraph::default();
+        let odd = egraph.add_expr(&"(* 3_i64 (| x_v 1_i64))".parse().unwrap());
+        let unknown = egraph.add_expr(&"(* 2_i64 x_v)".parse().unwrap());
+        egraph.rebuild();
+        assert!(egraph[odd].data.odd && egraph[odd].data.nonzero);
+        assert!(!egraph[unknown].data.odd && !egraph[unknown].data.nonzero);
+    }
+
+    #[test]
+    fn variance() {
+        let mut egraph = EGraph::default();
+        let invariant = egraph.add_expr(&"(+ x_v (* 2_i64 y_v))".parse().unwrap());
+        let variant = egraph.add_expr(&"(+ x_v (theta 0_i64 y_v))".parse().unwrap());
+        egraph.rebuild();
+        assert!(!egraph[invariant].data.variant);
+        assert!(egraph[variant].data.variant);
+    }
+
+    #[test]
+    fn boolean_functions() {
+        let mut egraph = EGraph::default();
+        let tautology = egraph.add_expr(&"(|| (< a_v b_v) (>= a_v b_v))".parse().unwrap());
+        let swapped = egraph.add_expr(&"(&& (! (<= b_v a_v)) c_v)".parse().unwrap());
+        let lt = egraph.add_expr(&"(&& c_v (< a_v b_v))".parse().unwrap());
+        egraph.rebuild();
+        assert_eq!(egraph[tautology].data.constant, Some(Constant::I1(true)));
+        assert_eq!(egraph.find(swapped), egraph.find(lt));
+    }
+
+    #[test]
+    fn integer_variables() {
+        let mut egraph = EGraph::default();
+        let int = egraph.add_expr(&"(phi c_v x_v y_v)".parse().unwrap());
+        let boolean = egraph.add_expr(&"(phi c_v d_v false)".parse().unwrap());
+        let and = egraph.add_expr(&"(&& c_v d_v)".parse().unwrap());
+        egraph.rebuild();
+        let x = egraph.lookup_expr(&"x_v".parse().unwrap()).unwrap();
+        assert_eq!(egraph[x].data.bdd, None);
+        assert_eq!(egraph[int].data.bdd, None);
+        assert_eq!(egraph.find(boolean), egraph.find(and));
+    }
+
+    #[test]
+    fn merged_variables() {
+        // `k` is only known to be a variable once it is merged with one
+        let mut egraph = EGraph::default();
+        let k = egraph.add_expr(&"(+ a_v 1_i64)".parse().unwrap());
+        let not = egraph.add(Lang::Not(k));
+        let phi = egraph.add_expr(&"(phi c_v false true)".parse().unwrap());
+        egraph.rebuild();
+        assert_eq!(egraph[not].data.bdd, None);
+        let c = egraph.lookup_expr(&"c_v".parse().unwrap()).unwrap();
+        egraph.union(k, c);
+        egraph.rebuild();
+        assert_eq!(egraph.find(not), egraph.find(phi));
+    }
+
+    #[test]
+    fn merged_atoms() {
+        let mut egraph = EGraph::default();
+        let lt = egraph.add_expr(&"(&& (< a_v c_v) d_v)".parse().unwrap());
+        let geq = egraph.add_expr(&"(&& d_v (! (>= b_v c_v)))".parse().unwrap());
+        let eq = egraph.add_expr(&"(== a_v b_v)".parse().unwrap());
+        egraph.rebuild();
+        assert_ne!(egraph.find(lt), egraph.find(geq));
+        let a = egraph.lookup_expr(&"a_v".parse().unwrap()).unwrap();
+        let b = egraph.lookup_expr(&"b_v".parse().unwrap()).unwrap();
+        egraph.union(a, b);
+        egraph.rebuild();
+        assert_eq!(egraph.find(lt), egraph.find(geq));
+        assert_eq!(egraph[eq].data.constant, Some(Constant::I1(true)));
+    }
+
+    #[test]
+    fn pointers() {
+        let mut egraph = EGraph::default();
+        let witness = egraph.add(Lang::Alloca(3));
+        let ptr = egraph.add(Lang::Ptr(witness));
+        egraph.rebuild();
+        assert_eq!(egraph[witness].data.alloca, Some(3));
+        assert_eq!(egraph[ptr].data.points_to, Some(3));
+        assert_eq!(egraph[ptr].data.alloca, None);
+    }
+}
@@ -0,0 +1,197 @@
+//! Reduced ordered binary decision diagrams over atomic conditions. Diagrams are plain trees
+//! compared structurally, which is canonical for a fixed atom order, so their size is capped.
+
+use std::sync::Arc;
+
+use egg::{Id, Symbol};
+
+use crate::lang::Cond;
+
+/// Diagrams larger than this are not built
+pub const MAX_SIZE: usize = 256;
+
+/// Atomic condition, ordered by the derived `Ord`. Comparisons are normalized to `==` and `<`
+/// over canonical e-class ids, so that `(> a b)` and `(! (<= a b))` share an atom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Atom {
+    Var(Symbol),
+    Eq(Id, Id),
+    Lt(Id, Id),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Bdd {
+    False,
+    True,
+    Node(Arc<Node>),
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Node {
+    atom: Atom,
+    low: Bdd,
+    high: Bdd,
+    size: usize,
+}
+
+impl Bdd {
+    pub fn constant(b: bool) -> Self {
+        if b {
+            Bdd::True
+        } else {
+            Bdd::False
+        }
+    }
+
+    pub fn atom(atom: Atom) -> Self {
+        Self::node(atom, Bdd::False, Bdd::True)
+    }
+
+    /// Comparison of two values, given by ids that are equal exactly for equal values
+    pub fn comparison(cond: Cond, l: Id, r: Id) -> Self {
+        let eq = || {
+            if l == r {
+                Bdd::True
+            } else {
+                Bdd::atom(Atom::Eq(l.min(r), l.max(r)))
+            }
+        };
+        let lt = |l: Id, r: Id| {
+            if l == r {
+                Bdd::False
+            } else {
+                Bdd::atom(Atom::Lt(l, r))
+            }
+        };
+        match cond {
+            Cond::Eq => eq(),
+            Cond::Neq => eq().not(),
+            Cond::Lt => lt(l, r),
+            Cond::Gt => lt(r, l),
+            Cond::Leq => lt(r, l).not(),
+            Cond::Geq => lt(l, r).not(),
+        }
+    }
+
+    fn node(atom: Atom, low: Bdd, high: Bdd) -> Self {
+        if low == high {
+            return low;
+        }
+        let size = 1 + low.size() + high.size();
+        Bdd::Node(Arc::new(Node {
+            atom,
+            low,
+            high,
+            size,
+        }))
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Bdd::False | Bdd::True => 0,
+            Bdd::Node(node) => node.size,
+        }
+    }
+
+    pub fn as_constant(&self) -> Option<bool> {
+        match self {
+            Bdd::False => Some(false),
+            Bdd::True => Some(true),
+            Bdd::Node(_) => None,
+        }
+    }
+
+    fn top(&self) -> Option<Atom> {
+        match self {
+            Bdd::Node(node) => Some(node.atom),
+            _ => None,
+        }
+    }
+
+    /// Cofactors with respect to `atom`, which must not be below the top atom
+    fn cofactors(&self, atom: Atom) -> (Bdd, Bdd) {
+        match self {
+            Bdd::Node(node) if node.atom == atom => (node.low.clone(), node.high.clone()),
+            _ => (self.clone(), self.clone()),
+        }
+    }
+
+    pub fn not(&self) -> Bdd {
+        match self {
+            Bdd::False => Bdd::True,
+            Bdd::True => Bdd::False,
+            Bdd::Node(node) => Self::node(node.atom, node.low.not(), node.high.not()),
+        }
+    }
+
+    pub fn and(&self, other: &Bdd) -> Option<Bdd> {
+        Self::ite(self, other, &Bdd::False)
+    }
+
+    pub fn or(&self, other: &Bdd) -> Option<Bdd> {
+        Self::ite(self, &Bdd::True, other)
+    }
+
+    /// `if cond then t else e`, `None` if the result exceeds [`MAX_SIZE`]
+    pub fn ite(cond: &Bdd, t: &Bdd, e: &Bdd) -> Option<Bdd> {
+        match (cond, t, e) {
+            (Bdd::True, _, _) => return Some(t.clone()),
+            (Bdd::False, _, _) => return Some(e.clone()),
+            _ if t == e => return Some(t.clone()),
+            (_, Bdd::True, Bdd::False) => return Some(cond.clone()),
+            (_, Bdd::False, Bdd::True) => return Some(cond.not()),
+            _ => {}
+        }
+
+        let top = [cond.top(), t.top(), e.top()]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap();
+        let (cond_low, cond_high) = cond.cofactors(top);
+        let (t_low, t_high) = t.cofactors(top);
+        let (e_low, e_high) = e.cofactors(top);
+        let low = Self::ite(&cond_low, &t_low, &e_low)?;
+        let high = Self::ite(&cond_high, &t_high, &e_high)?;
+        Some(Self::node(top, low, high)).filter(|bdd| bdd.size() <= MAX_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> Bdd {
+        Bdd::atom(Atom::Var(name.into()))
+    }
+
+    #[test]
+    fn canonical() {
+        let (a, b, c) = (var("a"), var("b"), var("c"));
+        // a && (b || c) == (a && b) || (a && c)
+        let lhs = a.and(&b.or(&c).unwrap()).unwrap();
+        let rhs = a.and(&b).unwrap().or(&a.and(&c).unwrap()).unwrap();
+        assert_eq!(lhs, rhs);
+        // !(a && b) == !a || !b
+        assert_eq!(a.and(&b).unwrap().not(), a.not().or(&b.not()).unwrap());
+    }
+
+    #[test]
+    fn constants() {
+        let a = var("a");
+        assert_eq!(a.or(&a.not()).unwrap().as_constant(), Some(true));
+        assert_eq!(a.and(&a.not()).unwrap().as_constant(), Some(false));
+        assert_eq!(Bdd::ite(&a, &Bdd::True, &Bdd::True), Some(Bdd::True));
+    }
+
+    #[test]
+    fn size_cap() {
+        // Parity of many variables has a diagram linear in their number, but the tree
+        // representation is exponential
+        let parity = (0..16).try_fold(Bdd::False, |acc, i| {
+            let v = var(&format!("v{}", i));
+            Bdd::ite(&v, &acc.not(), &acc)
+        });
+        assert_eq!(parity, None);
+    }
+}
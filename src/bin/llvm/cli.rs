@@ -0,0 +1,283 @@
+//! Command line of the `llvm` binary
+
+use lang::cost_fn::CostModel;
+use lang::interp::Value;
+use lang::rules::RuleSelection;
+
+pub const USAGE: &str = "\
+Usage: llvm <COMMAND> [OPTIONS] <INPUT.ll|INPUT.bc|INPUT.peg>
+       llvm equiv [OPTIONS] <LEFT> <RIGHT>
+
+Commands:
+  parse     Print the PEG of the function, in the format read from `.peg` files
+  saturate  Run the rules and print statistics of the saturation
+  extract   Print the cheapest term after saturation
+  interp    Evaluate the PEG, with `--arg NAME=VALUE` for every parameter
+  dot       Render the PEG, or the saturated e-graph with `--saturated`
+  opt       Saturate, extract and print the result as LLVM IR. The extracted term must be free
+            of memory operations
+  equiv     Check that two functions are equivalent, matching parameters by position
+
+Options:
+  --function NAME        Function to process, the first one by default
+  --right-function NAME  Function of the right input of `equiv`, `--function` by default
+  -o, --output PATH      Write to PATH instead of stdout. `dot` renders to .pdf, .svg or .png
+                         files by extension, and writes dot otherwise
+  --arg NAME=VALUE       Parameter value for `interp`, an integer, `true` or `false`
+  --saturated            Render the saturated e-graph with `dot`
+  --rules FILE           Load additional rules from FILE
+  --no-builtin-rules     Only use the rules loaded with `--rules`
+  --group NAME           Select a group of built-in rules
+  --exclude-group NAME   Exclude a group of built-in rules
+  --rule NAME            Select a single built-in rule
+  --exclude-rule NAME    Exclude a single built-in rule
+  --list-rules           Print the built-in rules and exit
+  --report FILE          Write statistics of the saturation to FILE as JSON
+  --cost PRESET|FILE     Cost model used for extraction, `size` or `speed` (default) or a
+                         JSON file
+  --alternatives K       Also print the K cheapest terms with `extract`
+  --linear               Only extract terms consuming every memory state once with `extract`,
+                         so that they can be emitted as sequential code
+  --explain              Print the rewrites turning the function into the extracted term with
+                         `extract` and `opt`
+  --explain-json FILE    Write the rewrites of `--explain` to FILE as JSON
+  -h, --help             Print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Parse,
+    Saturate,
+    Extract,
+    Interp,
+    Dot,
+    Opt,
+    Equiv,
+}
+
+/// What the command line asks for
+#[derive(Debug)]
+pub enum Invocation {
+    Run(Args),
+    Help,
+    ListRules,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    pub input: String,
+    pub right_input: Option<String>,
+    pub function: Option<String>,
+    pub right_function: Option<String>,
+    pub output: Option<String>,
+    pub interp_args: Vec<(String, Value)>,
+    pub saturated: bool,
+    pub rule_files: Vec<String>,
+    pub builtin_rules: bool,
+    pub selection: RuleSelection,
+    pub report: Option<String>,
+    pub cost: CostModel,
+    pub alternatives: Option<usize>,
+    pub linear: bool,
+    pub explain: bool,
+    pub explain_json: Option<String>,
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+    let mut command = None;
+    let mut input = None;
+    let mut right_input = None;
+    let mut function = None;
+    let mut right_function = None;
+    let mut output = None;
+    let mut interp_args = vec![];
+    let mut saturated = false;
+    let mut rule_files = vec![];
+    let mut builtin_rules = true;
+    let mut selection = RuleSelection::default();
+    let mut report = None;
+    let mut cost = CostModel::preset("speed").unwrap();
+    let mut alternatives = None;
+    let mut linear = false;
+    let mut explain = false;
+    let mut explain_json = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| format!("no value provided for {}", flag))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Invocation::Help),
+            "--list-rules" => return Ok(Invocation::ListRules),
+            "--function" => function = Some(value(&arg)?),
+            "--right-function" => right_function = Some(value(&arg)?),
+            "-o" | "--output" => output = Some(value(&arg)?),
+            "--arg" => interp_args.push(parse_interp_arg(&value(&arg)?)?),
+            "--saturated" => saturated = true,
+            "--rules" => rule_files.push(value(&arg)?),
+            "--no-builtin-rules" => builtin_rules = false,
+            "--group" => selection = selection.include_group(value(&arg)?),
+            "--exclude-group" => selection = selection.exclude_group(value(&arg)?),
+            "--rule" => selection = selection.include_rule(value(&arg)?),
+            "--exclude-rule" => selection = selection.exclude_rule(value(&arg)?),
+            "--report" => report = Some(value(&arg)?),
+            "--cost" => cost = CostModel::load(&value(&arg)?)?,
+            "--alternatives" => {
+                let k = value(&arg)?;
+                let k = k
+                    .parse()
+                    .map_err(|_| format!("expected a number of alternatives, found `{}`", k))?;
+                alternatives = Some(k);
+            }
+            "--linear" => linear = true,
+            "--explain" => explain = true,
+            "--explain-json" => explain_json = Some(value(&arg)?),
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+            _ if command.is_none() => command = Some(parse_command(&arg)?),
+            _ if input.is_none() => input = Some(arg),
+            _ if command == Some(Command::Equiv) && right_input.is_none() => {
+                right_input = Some(arg)
+            }
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    let command = command.ok_or("no command provided")?;
+    if command == Command::Equiv && right_input.is_none() {
+        return Err("`equiv` takes two input files".to_string());
+    }
+    if linear && command != Command::Extract {
+        return Err("`--linear` only applies to `extract`".to_string());
+    }
+    Ok(Invocation::Run(Args {
+        command,
+        input: input.ok_or("no input file provided")?,
+        right_input,
+        function,
+        right_function,
+        output,
+        interp_args,
+        saturated,
+        rule_files,
+        builtin_rules,
+        selection,
+        report,
+        cost,
+        alternatives,
+        linear,
+        explain,
+        explain_json,
+    }))
+}
+
+fn parse_command(command: &str) -> Result<Command, String> {
+    match command {
+        "parse" => Ok(Command::Parse),
+        "saturate" => Ok(Command::Saturate),
+        "extract" => Ok(Command::Extract),
+        "interp" => Ok(Command::Interp),
+        "dot" => Ok(Command::Dot),
+        "opt" => Ok(Command::Opt),
+        "equiv" => Ok(Command::Equiv),
+        other => Err(format!("unknown command `{}`", other)),
+    }
+}
+
+fn parse_interp_arg(arg: &str) -> Result<(String, Value), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected `NAME=VALUE`, found `{}`", arg))?;
+    let value = match value {
+        "true" => Value::I1(true),
+        "false" => Value::I1(false),
+        _ => Value::I64(
+            value
+                .parse()
+                .map_err(|_| format!("invalid value `{}` for `{}`", value, name))?,
+        ),
+    };
+    Ok((name.to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_strs(args: &[&str]) -> Result<Invocation, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn args(args: &[&str]) -> Args {
+        match parse_strs(args) {
+            Ok(Invocation::Run(args)) => args,
+            Ok(other) => panic!("expected a command, found {:?}", other),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse_strs(args) {
+            Ok(parsed) => panic!("expected an error, found {:?}", parsed),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let commands = [
+            ("parse", Command::Parse),
+            ("saturate", Command::Saturate),
+            ("extract", Command::Extract),
+            ("interp", Command::Interp),
+            ("dot", Command::Dot),
+            ("opt", Command::Opt),
+        ];
+        for (name, command) in commands {
+            let args = args(&[name, "f.ll"]);
+            assert_eq!((args.command, args.input.as_str()), (command, "f.ll"));
+        }
+        let equiv = args(&["equiv", "a.ll", "--function", "f", "b.peg"]);
+        assert_eq!(equiv.command, Command::Equiv);
+        assert_eq!(equiv.right_input.as_deref(), Some("b.peg"));
+        assert_eq!(equiv.function.as_deref(), Some("f"));
+        assert!(error(&["run", "f.ll"]).contains("unknown command `run`"));
+        assert!(matches!(
+            parse_strs(&["extract", "--help"]),
+            Ok(Invocation::Help)
+        ));
+        assert!(matches!(
+            parse_strs(&["--list-rules"]),
+            Ok(Invocation::ListRules)
+        ));
+    }
+
+    #[test]
+    fn interp_args() {
+        let args = args(&["interp", "f.ll", "--arg", "x=-3", "--arg", "c=true"]);
+        let expected = [
+            ("x".to_string(), Value::I64(-3)),
+            ("c".to_string(), Value::I1(true)),
+        ];
+        assert_eq!(args.interp_args, expected);
+        assert!(error(&["interp", "f.ll", "--arg", "x"]).contains("expected `NAME=VALUE`"));
+        assert!(error(&["interp", "f.ll", "--arg", "x=y"]).contains("invalid value `y`"));
+        assert!(error(&["interp", "f.ll", "--arg"]).contains("no value provided for --arg"));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(error(&["extract", "--bogus", "f.ll"]).contains("unknown option `--bogus`"));
+        assert!(error(&["extract", "f.ll", "g.ll"]).contains("unexpected argument `g.ll`"));
+        assert!(error(&["extract", "f.ll", "--alternatives", "some"]).contains("`some`"));
+        assert_eq!(error(&["extract"]), "no input file provided");
+        assert_eq!(error(&[]), "no command provided");
+        assert_eq!(error(&["equiv", "a.ll"]), "`equiv` takes two input files");
+        assert!(args(&["extract", "f.ll", "--linear"]).linear);
+        assert_eq!(
+            error(&["opt", "f.ll", "--linear"]),
+            "`--linear` only applies to `extract`"
+        );
+    }
+}
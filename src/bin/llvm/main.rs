@@ -1,68 +1,278 @@
-use egg::{AstDepth, AstSize, Extractor, Runner};
-use lang::conv::to_epeg::parse_function;
-use lang::rules::rw_rules;
-use lang::EGraph;
-use std::env;
-use std::io::stdin;
+use std::fmt::Write;
 use std::path::Path;
 
+use egg::{CostFunction, Extractor, Id, Language, RecExpr};
+use lang::conv::{to_epeg::parse_function, to_llvm};
+use lang::cost_fn::CostModel;
+use lang::equiv::check_equivalence;
+use lang::explain::explain;
+use lang::extract::linear::find_best_linear;
+use lang::extract::multi::{pareto, top_k};
+use lang::function_file;
+use lang::interp;
+use lang::optimizer::{Optimizer, Phase, Saturated};
+use lang::rule_file::load_rules;
+use lang::rules::rule_groups;
+use lang::{EGraph, Function, Lang};
+
+use cli::{Args, Command, Invocation};
+
+mod cli;
+
+/// Terms kept per class by `--linear` extraction
+const LINEAR_CANDIDATES: usize = 8;
+
 fn main() {
-    let file = env::args().nth(1).expect("No LLVM bytecode file provided");
-    // let file = "llvm_programs/triple_if/triple_if.bc";
-    // let file = "llvm_programs/complex_ifs/complex_ifs.bc";
-    let path = Path::new(&file);
-    dbg!(&path);
-    let module = llvm_ir::Module::from_bc_path(&file).unwrap();
-    // dbg!(&module.functions);
-
-    let func = &module.functions[0];
-
-    let lang::Function { body: egraph, .. } = parse_function(func);
-
-    // let extractor = Extractor::new(&egraph, AstSize);
-    // let (best_cost, best) = extractor.find_best(root);
-    // println!("Extracted: \n{}", best.pretty(20));
-    // egraph.dot().to_pdf("examples/if.pdf").unwrap();
-
-    println!("Parsed");
-    let mut initial_expr = EGraph::default();
-    let root = initial_expr.add_expr(&egraph);
-    initial_expr.dot().to_pdf("/tmp/parsed.pdf").unwrap();
-
-    let runner = Runner::default()
-        .with_node_limit(100000)
-        // .with_time_limit(std::time::Duration::from_secs(15))
-        .with_iter_limit(100)
-        .with_expr(&egraph)
-        .run(&rw_rules());
-    println!("Runner finished");
-    dbg!(runner.stop_reason.unwrap());
-    // runner.egraph.dot().to_pdf("/tmp/saturated.pdf").unwrap();
-
-    println!("Root: {:?}", &root);
-    println!("Root: {:?}", &runner.roots);
-    // println!("Egraph: \n {:?}", &runner.egraph);
-    let extractor = Extractor::new(&runner.egraph, lang::cost_fn::NoAlloc);
-    let (best_cost, best) = extractor.find_best(runner.roots[0]);
-
-    println!("Extracted: \n{}", best.pretty(20));
-
-    let mut extracted = EGraph::default();
-    extracted.add_expr(&best);
-    extracted.dot().to_pdf("/tmp/extracted.pdf").unwrap();
-
-    // let mut egraph = EGraph::default();
-    // let root = egraph.add_expr(&best);
-    // egraph.dot().to_pdf("examples/if.pdf").unwrap();
-    //
-    // let runner = Runner::default()
-    //     .with_node_limit(1000000)
-    //     .with_time_limit(std::time::Duration::from_secs(15))
-    //     .with_iter_limit(100)
-    //     .with_egraph(egraph)
-    //     .run(&rw_rules());
-    // let extractor = Extractor::new(&runner.egraph, AstSize);
-    // let (best_cost, best) = extractor.find_best(root);
-    // println!("Extracted: \n{}", best.pretty(20));
-    // runner.egraph.dot().to_pdf("examples/if.pdf").unwrap();
+    let args = match cli::parse(std::env::args().skip(1)) {
+        Ok(Invocation::Run(args)) => args,
+        Ok(Invocation::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Invocation::ListRules) => {
+            for (group, rules) in rule_groups() {
+                println!("{}:", group);
+                for rule in rules {
+                    println!("  {}", rule.name);
+                }
+            }
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = run(&args) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let function = load_function(&args.input, args.function.as_deref())?;
+    match args.command {
+        Command::Parse => output(args, &function.to_string()),
+        Command::Saturate => {
+            let saturated = saturate(args, &function)?;
+            output(args, &saturated.report().to_string())
+        }
+        Command::Extract => {
+            let mut saturated = saturate(args, &function)?;
+            let root = saturated.roots[0];
+            let mut text = match args.alternatives {
+                Some(k) => alternatives(&saturated.egraph, root, k, &args.cost),
+                None => String::new(),
+            };
+            let (cost, best) = extract_best(args, &saturated, &function);
+            if let Some(proof) = explain_extraction(args, &mut saturated, &function, &best)? {
+                text += &proof;
+            }
+            write!(text, "Cost: {:.2}\n{}", cost, best.pretty(40)).unwrap();
+            output(args, &text)
+        }
+        Command::Interp => {
+            let mut env = interp::Env::default();
+            for (name, value) in &args.interp_args {
+                if !function.params.contains(name) {
+                    return Err(format!("`{}` has no parameter `{}`", function.name, name));
+                }
+                env.set(name.as_str().into(), *value);
+            }
+            let expr = interp::Expr::with_root(&function.body, function.root);
+            let value = expr.interp(&env, &mut interp::Store::default())?;
+            output(args, &format!("{:?}", value))
+        }
+        Command::Dot => {
+            let egraph = if args.saturated {
+                saturate(args, &function)?.egraph
+            } else {
+                let mut egraph = EGraph::default();
+                add_rooted(&mut egraph, &function.body);
+                egraph.rebuild();
+                egraph
+            };
+            write_dot(&egraph, args.output.as_deref())
+        }
+        Command::Opt => {
+            let mut saturated = saturate(args, &function)?;
+            let (_, best) = extract_best(args, &saturated, &function);
+            if let Some(proof) = explain_extraction(args, &mut saturated, &function, &best)? {
+                eprint!("{}", proof);
+            }
+            let optimized = Function {
+                root: Id::from(best.as_ref().len() - 1),
+                body: best,
+                ..function
+            };
+            output(args, &to_llvm::emit(&optimized)?)
+        }
+        Command::Equiv => {
+            let right_input = args.right_input.as_deref().expect("checked when parsing");
+            let right_name = args.right_function.as_deref().or(args.function.as_deref());
+            let right = load_function(right_input, right_name)?;
+            let verdict = check_equivalence(&function, &right, &optimizer(args)?)?;
+            output(args, &verdict.to_string())
+        }
+    }
+}
+
+/// Parses textual (`.ll`) or bitcode (`.bc`) modules, and picks the named function or the first
+/// one. Functions saved by `parse` (`.peg`) are loaded as they are.
+fn load_function(input: &str, name: Option<&str>) -> Result<Function, String> {
+    let module = match Path::new(input).extension().and_then(|ext| ext.to_str()) {
+        Some("ll") => llvm_ir::Module::from_ir_path(input),
+        Some("bc") => llvm_ir::Module::from_bc_path(input),
+        Some("peg") => return function_file::load_function(input),
+        _ => return Err(format!("{}: expected a `.ll`, `.bc` or `.peg` file", input)),
+    }
+    .map_err(|err| format!("cannot parse {}: {}", input, err))?;
+    let func = match name {
+        Some(name) => module
+            .get_func_by_name(name)
+            .ok_or_else(|| format!("{} has no function `{}`", input, name))?,
+        None => module
+            .functions
+            .first()
+            .ok_or_else(|| format!("{} has no functions", input))?,
+    };
+    Ok(parse_function(func))
+}
+
+fn optimizer(args: &Args) -> Result<Optimizer, String> {
+    let mut rules = if args.builtin_rules {
+        args.selection.select()?
+    } else {
+        vec![]
+    };
+    for rule_file in &args.rule_files {
+        rules.extend(load_rules(rule_file)?);
+    }
+
+    let optimizer = Optimizer::default().with_phase(Phase::new("rules", rules));
+    if args.explain || args.explain_json.is_some() {
+        Ok(optimizer.with_explanations_enabled())
+    } else {
+        Ok(optimizer)
+    }
+}
+
+fn saturate(args: &Args, function: &Function) -> Result<Saturated, String> {
+    let saturated = optimizer(args)?.run_function(function);
+    if let Some(path) = &args.report {
+        std::fs::write(path, saturated.report().to_json())
+            .map_err(|err| format!("cannot write {}: {}", path, err))?;
+    }
+    Ok(saturated)
+}
+
+/// Body of the function as a term rooted at its last node
+fn original(function: &Function) -> RecExpr<Lang> {
+    let body = &function.body;
+    body[function.root].build_recexpr(|id| body[id].clone())
+}
+
+/// Cheapest term of the function under the cost model, among the linear ones with `--linear`
+fn extract_best(args: &Args, saturated: &Saturated, function: &Function) -> (f64, RecExpr<Lang>) {
+    let root = saturated.roots[0];
+    if args.linear {
+        let best = find_best_linear(
+            &saturated.egraph,
+            root,
+            LINEAR_CANDIDATES,
+            args.cost.clone(),
+        );
+        match best {
+            Some(best) => (best.cost, best.expr),
+            None => {
+                eprintln!(
+                    "warning: no linear term within the bounds of extraction, keeping the input"
+                );
+                let original = original(function);
+                (args.cost.clone().cost_rec(&original), original)
+            }
+        }
+    } else {
+        Extractor::new(&saturated.egraph, args.cost.clone()).find_best(root)
+    }
+}
+
+/// Writes the rewrites from the function to the extracted term if requested, and returns them as
+/// text with `--explain`
+fn explain_extraction(
+    args: &Args,
+    saturated: &mut Saturated,
+    function: &Function,
+    extracted: &RecExpr<Lang>,
+) -> Result<Option<String>, String> {
+    if !args.explain && args.explain_json.is_none() {
+        return Ok(None);
+    }
+    let proof = explain(&mut saturated.egraph, &original(function), extracted)?;
+    if let Some(path) = &args.explain_json {
+        std::fs::write(path, proof.to_json())
+            .map_err(|err| format!("cannot write {}: {}", path, err))?;
+    }
+    Ok(args.explain.then(|| proof.to_string()))
+}
+
+/// Adds every node of the expression, which need not be rooted at its last node
+fn add_rooted(egraph: &mut EGraph, expr: &RecExpr<Lang>) {
+    let mut ids: Vec<Id> = vec![];
+    for node in expr.as_ref() {
+        let node = node.clone().map_children(|child| ids[usize::from(child)]);
+        ids.push(egraph.add(node));
+    }
+}
+
+/// Lists the `k` cheapest programs under the cost model, and the programs trading the cost
+/// model against size, or against speed if the cost model is the size one
+fn alternatives(egraph: &EGraph, root: Id, k: usize, cost: &CostModel) -> String {
+    let mut text = format!("Cheapest {} programs:\n", k);
+    for (i, candidate) in top_k(egraph, root, k, cost.clone()).iter().enumerate() {
+        let (cost, expr) = (candidate.cost, candidate.expr.pretty(40));
+        writeln!(text, "#{} cost {:.2}:\n{}", i + 1, cost, expr).unwrap();
+    }
+    let size = CostModel::preset("size").unwrap();
+    let (axis, other) = if *cost == size {
+        ("speed", CostModel::preset("speed").unwrap())
+    } else {
+        ("size", size)
+    };
+    writeln!(text, "Cost/{} trade-offs:", axis).unwrap();
+    for candidate in pareto(egraph, root, k, cost.clone(), other) {
+        let (cost, other) = candidate.cost;
+        let expr = candidate.expr.pretty(40);
+        writeln!(text, "cost {:.2}, {} {:.2}:\n{}", cost, axis, other, expr).unwrap();
+    }
+    text
+}
+
+/// Writes to the output file if one was given, to stdout otherwise
+fn output(args: &Args, text: &str) -> Result<(), String> {
+    match &args.output {
+        Some(path) => std::fs::write(path, format!("{}\n", text))
+            .map_err(|err| format!("cannot write {}: {}", path, err)),
+        None => {
+            println!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+/// Renders the e-graph in the format given by the extension of the output file, or prints it in
+/// the dot format
+fn write_dot(egraph: &EGraph, output: Option<&str>) -> Result<(), String> {
+    let dot = egraph.dot();
+    let Some(path) = output else {
+        println!("{}", dot);
+        return Ok(());
+    };
+    let written = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("pdf") => dot.to_pdf(path),
+        Some("svg") => dot.to_svg(path),
+        Some("png") => dot.to_png(path),
+        _ => dot.to_dot(path),
+    };
+    written.map_err(|err| format!("cannot write {}: {}", path, err))
 }
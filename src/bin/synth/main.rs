@@ -0,0 +1,20 @@
+use lang::synth::{synthesize, to_rule_file, SynthConfig};
+use std::env;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let output = args.next().expect("No output rule file provided");
+    let depth = args
+        .next()
+        .map(|depth| depth.parse().expect("Depth must be a number"))
+        .unwrap_or(2);
+
+    let config = SynthConfig {
+        depth,
+        ..Default::default()
+    };
+    let rules = synthesize(&config);
+    println!("Synthesized {} rules", rules.len());
+
+    std::fs::write(&output, to_rule_file(&rules)).unwrap();
+}
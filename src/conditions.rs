@@ -0,0 +1,102 @@
+//! Predicates guarding conditional rewrites. Every predicate is usable as an `if` clause of
+//! `egg::rewrite!` and, by name, as a condition in rule files.
+
+use std::sync::Arc;
+
+use egg::{Condition, Id, Subst, Var};
+
+use crate::analysis::Constant;
+use crate::{EGraph, Lang};
+
+pub type Predicate = dyn Fn(&mut EGraph, Id, &Subst) -> bool + Send + Sync;
+
+/// Conjunction of predicates
+#[derive(Clone, Default)]
+pub struct Conditions {
+    predicates: Vec<Arc<Predicate>>,
+    vars: Vec<Var>,
+}
+
+impl Conditions {
+    pub fn push(&mut self, predicate: Arc<Predicate>, vars: Vec<Var>) {
+        self.predicates.push(predicate);
+        self.vars.extend(vars);
+    }
+}
+
+impl Condition<Lang, crate::analysis::LangAnalysis> for Conditions {
+    fn check(&self, egraph: &mut EGraph, eclass: Id, subst: &Subst) -> bool {
+        self.predicates.iter().all(|p| p(egraph, eclass, subst))
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.vars.clone()
+    }
+}
+
+/// Looks up a predicate by the name used in rule files
+pub fn by_name(name: &str, vars: &[Var]) -> Option<Arc<Predicate>> {
+    match (name, vars) {
+        ("equal", &[a, b]) => Some(Arc::new(move |egraph: &mut EGraph, _: Id, subst: &Subst| {
+            egraph.find(subst[a]) == egraph.find(subst[b])
+        })),
+        ("distinct", &[a, b]) => Some(Arc::new(move |egraph: &mut EGraph, _: Id, subst: &Subst| {
+            egraph.find(subst[a]) != egraph.find(subst[b])
+        })),
+        ("const", &[a]) => Some(Arc::new(is_const(a))),
+        ("pow2", &[a]) => Some(Arc::new(is_power_of_two(a))),
+        ("odd", &[a]) => Some(Arc::new(is_odd(a))),
+        ("nonzero", &[a]) => Some(Arc::new(is_nonzero(a))),
+        ("invariant", &[a]) => Some(Arc::new(is_invariant(a))),
+        ("noalias", &[a, b]) => Some(Arc::new(distinct_pointers(a, b))),
+        _ => None,
+    }
+}
+
+pub fn var(name: &str) -> Var {
+    name.parse().expect("valid pattern variable")
+}
+
+fn int_constant(egraph: &EGraph, id: Id) -> Option<i64> {
+    egraph[id].data.constant.and_then(Constant::as_i64)
+}
+
+/// The variable is bound to a constant
+pub fn is_const(var: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    move |egraph, _, subst| egraph[subst[var]].data.constant.is_some()
+}
+
+/// The variable is bound to a positive power of two greater than one
+pub fn is_power_of_two(var: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    move |egraph, _, subst| {
+        int_constant(egraph, subst[var]).map_or(false, |c| c > 1 && (c & (c - 1)) == 0)
+    }
+}
+
+/// The variable is bound to an integer known to be odd
+pub fn is_odd(var: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    move |egraph, _, subst| egraph[subst[var]].data.odd
+}
+
+/// The variable is bound to an integer known to be non-zero
+pub fn is_nonzero(var: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    move |egraph, _, subst| egraph[subst[var]].data.nonzero
+}
+
+/// The variable is bound to a value that does not change across loop iterations
+pub fn is_invariant(var: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    move |egraph, _, subst| !egraph[subst[var]].data.variant
+}
+
+/// Both variables are bound to pointers known to point into different allocations
+pub fn distinct_pointers(a: Var, b: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    move |egraph, _, subst| {
+        match (
+            egraph[subst[a]].data.points_to,
+            egraph[subst[b]].data.points_to,
+        ) {
+            (Some(a), Some(b)) => a != b,
+            _ => false,
+        }
+    }
+}
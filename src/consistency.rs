@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use egg::{FlatTerm, Id, Language, RecExpr};
+
+use crate::interp::{Env, Expr, Store, Value};
+use crate::{EGraph, Lang};
+
+/// An e-class whose e-nodes evaluate to different values under the same environment
+#[derive(Debug, Clone)]
+pub struct Inconsistency {
+    pub class: Id,
+    pub env: Env,
+    /// Term built around the first e-node of the class and its value
+    pub expected: (RecExpr<Lang>, Value),
+    /// Term built around the first disagreeing e-node and its value
+    pub found: (RecExpr<Lang>, Value),
+    /// Rewrite responsible for the merge, available if explanations are enabled
+    pub rule: Option<String>,
+}
+
+/// Evaluates every e-node of every e-class under each of the given environments and reports
+/// classes whose e-nodes disagree. Children of an e-node are instantiated with the smallest
+/// term of their class, so only values that do not depend on node identity (integers and
+/// booleans) are compared.
+pub fn check_consistency(egraph: &mut EGraph, envs: &[Env]) -> Vec<Inconsistency> {
+    let terms = node_terms(egraph);
+
+    let mut inconsistencies = vec![];
+    for (class, terms) in terms {
+        for env in envs {
+            let mut values = terms
+                .iter()
+                .filter_map(|term| comparable(interp(term, env)).map(|val| (term, val)));
+            let Some((expected, expected_val)) = values.next() else {
+                continue;
+            };
+            let Some((found, found_val)) = values.find(|(_, val)| *val != expected_val) else {
+                continue;
+            };
+
+            let rule = if egraph.are_explanations_enabled() {
+                blame_rule(egraph, expected, found, env)
+            } else {
+                None
+            };
+            inconsistencies.push(Inconsistency {
+                class,
+                env: env.clone(),
+                expected: (expected.clone(), expected_val),
+                found: (found.clone(), found_val),
+                rule,
+            });
+            break;
+        }
+    }
+    inconsistencies
+}
+
+fn interp(expr: &RecExpr<Lang>, env: &Env) -> Result<Value, String> {
+    Expr::new(expr).interp(env, &mut Store::default())
+}
+
+/// Pointers and witnesses are identified by their position in the term, so they cannot be
+/// compared across terms
+fn comparable(val: Result<Value, String>) -> Option<Value> {
+    match val {
+        Ok(val @ (Value::I64(_) | Value::I1(_))) => Some(val),
+        _ => None,
+    }
+}
+
+/// Walks the explanation of `from = to` and returns the first rewrite that changes the value
+/// of the term
+fn blame_rule(
+    egraph: &mut EGraph,
+    from: &RecExpr<Lang>,
+    to: &RecExpr<Lang>,
+    env: &Env,
+) -> Option<String> {
+    let mut explanation = egraph.explain_equivalence(from, to);
+    let steps = explanation.make_flat_explanation();
+
+    let mut prev = comparable(interp(&flat_to_expr(&steps[0]), env));
+    for step in &steps[1..] {
+        let val = comparable(interp(&flat_to_expr(step), env));
+        if val.is_some() && prev.is_some() && val != prev {
+            return applied_rule(step);
+        }
+        prev = val.or(prev);
+    }
+    None
+}
+
+/// Name of the rewrite applied in an explanation step
+pub(crate) fn applied_rule(term: &FlatTerm<Lang>) -> Option<String> {
+    if let Some(rule) = term.forward_rule.or(term.backward_rule) {
+        return Some(rule.to_string());
+    }
+    term.children.iter().find_map(applied_rule)
+}
+
+pub(crate) fn flat_to_expr(term: &FlatTerm<Lang>) -> RecExpr<Lang> {
+    fn add(term: &FlatTerm<Lang>, expr: &mut RecExpr<Lang>) -> Id {
+        let children: Vec<_> = term.children.iter().map(|c| add(c, expr)).collect();
+        let mut children = children.into_iter();
+        let node = term.node.clone().map_children(|_| children.next().unwrap());
+        expr.add(node)
+    }
+
+    let mut expr = RecExpr::default();
+    add(term, &mut expr);
+    expr
+}
+
+/// For every e-class, builds one term per e-node with the children replaced by the smallest
+/// term of their class. E-nodes that only occur in cycles are skipped.
+fn node_terms(egraph: &EGraph) -> Vec<(Id, Vec<RecExpr<Lang>>)> {
+    let best = smallest_nodes(egraph);
+
+    egraph
+        .classes()
+        .map(|class| {
+            let terms = class
+                .iter()
+                .filter(|node| node.all(|child| best.contains_key(&egraph.find(child))))
+                .map(|node| {
+                    let mut builder = TermBuilder::default();
+                    let node = node
+                        .clone()
+                        .map_children(|child| builder.add_class(egraph, &best, child));
+                    builder.add(node);
+                    builder.expr
+                })
+                .collect();
+            (class.id, terms)
+        })
+        .collect()
+}
+
+/// Smallest e-node of each e-class by AST size
+fn smallest_nodes(egraph: &EGraph) -> HashMap<Id, (usize, Lang)> {
+    let mut best: HashMap<Id, (usize, Lang)> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for class in egraph.classes() {
+            for node in class.iter() {
+                if !node.all(|child| best.contains_key(&egraph.find(child))) {
+                    continue;
+                }
+                let size = node.fold(1, |acc, child| acc + best[&egraph.find(child)].0);
+                if best.get(&class.id).map_or(true, |(old, _)| size < *old) {
+                    best.insert(class.id, (size, node.clone()));
+                    changed = true;
+                }
+            }
+        }
+    }
+    best
+}
+
+/// Builds a term while sharing identical subterms, so that pointers and witnesses keep their
+/// identity in the interpreter
+#[derive(Default)]
+struct TermBuilder {
+    expr: RecExpr<Lang>,
+    memo: HashMap<Lang, Id>,
+}
+
+impl TermBuilder {
+    fn add(&mut self, node: Lang) -> Id {
+        if let Some(&id) = self.memo.get(&node) {
+            return id;
+        }
+        let id = self.expr.add(node.clone());
+        self.memo.insert(node, id);
+        id
+    }
+
+    fn add_class(&mut self, egraph: &EGraph, best: &HashMap<Id, (usize, Lang)>, class: Id) -> Id {
+        let (_, node) = &best[&egraph.find(class)];
+        let node = node
+            .clone()
+            .map_children(|child| self.add_class(egraph, best, child));
+        self.add(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{Optimizer, Phase};
+    use egg::rewrite;
+
+    fn env_x(x: i64) -> Env {
+        let mut env = Env::default();
+        env.set("x".into(), Value::I64(x));
+        env
+    }
+
+    #[test]
+    fn sound_rules_are_consistent() {
+        let expr = "(* 1_i64 (+ x_v 0_i64))".parse().unwrap();
+        let mut saturated = Optimizer::default()
+            .with_explanations_enabled()
+            .run_expr(&expr);
+
+        let found = check_consistency(&mut saturated.egraph, &[env_x(3), env_x(-2)]);
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn unsound_rule_is_named() {
+        let expr = "(+ (* 2_i64 x_v) 1_i64)".parse().unwrap();
+        let rules = vec![
+            rewrite!("commutative addition"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+            rewrite!("bogus"; "(* 2_i64 ?a)" => "?a"),
+        ];
+        let mut saturated = Optimizer::default()
+            .with_explanations_enabled()
+            .with_phase(Phase::new("rules", rules))
+            .run_expr(&expr);
+
+        let found = check_consistency(&mut saturated.egraph, &[env_x(3)]);
+        assert!(!found.is_empty());
+        assert!(found.iter().all(|i| i.rule.as_deref() == Some("bogus")));
+    }
+}
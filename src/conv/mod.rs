@@ -1,4 +1,4 @@
 mod cfg;
 mod loops;
 pub mod to_epeg;
-mod to_llvm;
+pub mod to_llvm;
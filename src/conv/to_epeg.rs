@@ -14,7 +14,9 @@ enum Operand {
 struct Context {
     to_id: HashMap<Operand, egg::Id>,
     alloc_ctr: u64,
-    ptr_state: Vec<HashMap<egg::Id, egg::Id>>, // block_id -> ptr -> witness
+    // block_id -> memory state, shared by all pointers so that loads and stores through
+    // different pointers are ordered against each other
+    mem_state: Vec<Option<egg::Id>>,
     block_cond: Vec<egg::Id>,
     ret: Option<egg::Id>,
     cfg: Cfg,
@@ -25,7 +27,7 @@ impl Context {
         Self {
             to_id,
             alloc_ctr: 0,
-            ptr_state: vec![HashMap::new(); bblocks.len()],
+            mem_state: vec![None; bblocks.len()],
             block_cond: vec![Default::default(); bblocks.len()],
             ret: None,
             cfg: Cfg::new(bblocks),
@@ -125,28 +127,16 @@ fn parse_bblock(ctx: &mut Context, egraph: &mut RecExprBuilder, bblock: &llvm_ir
                 .iter()
                 .map(|&pred| (pred, ctx.block_cond[pred]))
                 .collect();
-            // Ptrs shared by all predecessors
-            let shared_ptrs = preds.iter().skip(1).fold(
-                ctx.ptr_state[preds[0]].keys().collect::<Vec<_>>(),
-                |acc, &pred| {
-                    acc.into_iter()
-                        .filter(|&ptr| ctx.ptr_state[pred].contains_key(ptr))
-                        .collect()
-                },
-            );
-
-            let mut ptr_state = HashMap::new();
-            for ptr in shared_ptrs {
-                // (block_id, block_cond)
-                let mut curr_witness = *ctx.ptr_state[preds[0]].get(ptr).unwrap();
-                for (pred_id, pred_cond) in &pred_conds[1..] {
-                    let witness = *ctx.ptr_state[*pred_id].get(ptr).unwrap();
-                    let id = egraph.add(Lang::Phi([*pred_cond, witness, curr_witness]));
-                    curr_witness = id;
-                }
-                ptr_state.insert(*ptr, curr_witness);
-            }
-            ctx.ptr_state[block_id] = ptr_state;
+            // Memory state of the predecessor the block was entered from
+            let mem_state = ctx.mem_state[preds[0]].and_then(|first| {
+                pred_conds[1..]
+                    .iter()
+                    .try_fold(first, |curr_state, (pred_id, pred_cond)| {
+                        let state = ctx.mem_state[*pred_id]?;
+                        Some(egraph.add(Lang::Phi([*pred_cond, state, curr_state])))
+                    })
+            });
+            ctx.mem_state[block_id] = mem_state;
 
             let get_pred_cond = |ctx: &mut Context, egraph: &mut RecExprBuilder, pred: usize| {
                 let term = ctx.cfg.blocks[pred].term.clone();
@@ -203,25 +193,23 @@ fn parse_instruction(
 
             let ptr = egraph.add(Lang::Ptr(witness));
             ctx.to_id.insert((&alloca.dest).into(), ptr);
-            ctx.ptr_state[block_id].insert(ptr, witness);
+            // The memory of later allocations is uninitialized in every state, so the first
+            // witness starts the state
+            ctx.mem_state[block_id].get_or_insert(witness);
         }
         llvm_ir::Instruction::Load(load) => {
             let ptr = ctx.get_or_add_id(egraph, &(&load.address).into());
-            let witness = *ctx.ptr_state[block_id]
-                .get(&ptr)
-                .expect("Pointer witness not found");
-            let id = egraph.add(Lang::Load([witness, ptr]));
+            let state = ctx.mem_state[block_id].expect("Memory state not found");
+            let id = egraph.add(Lang::Load([state, ptr]));
             ctx.to_id.insert((&load.dest).into(), id);
-            // Loads do not affect state, so no need to update ptr_state
+            // Loads do not affect state, so no need to update mem_state
         }
         llvm_ir::Instruction::Store(store) => {
             let ptr = ctx.get_or_add_id(egraph, &(&store.address).into());
             let value = ctx.get_or_add_id(egraph, &(&store.value).into());
-            let witness = *ctx.ptr_state[block_id]
-                .get(&ptr)
-                .expect("Pointer witness not found");
-            let id = egraph.add(Lang::Store([value, witness, ptr]));
-            ctx.ptr_state[block_id].insert(ptr, id); // Now this load is the witness
+            let state = ctx.mem_state[block_id].expect("Memory state not found");
+            let id = egraph.add(Lang::Store([value, state, ptr]));
+            ctx.mem_state[block_id] = Some(id); // Now this store is the memory state
         }
         llvm_ir::Instruction::ICmp(icmp) => {
             let op0 = ctx.get_or_add_id(egraph, &(&icmp.operand0).into());
@@ -256,6 +244,12 @@ fn parse_instruction(
             let id = egraph.add(Lang::Mul([op0, op1]));
             ctx.to_id.insert((&mul.dest).into(), id);
         }
+        llvm_ir::Instruction::Shl(shl) => {
+            let op0 = ctx.get_or_add_id(egraph, &(&shl.operand0).into());
+            let op1 = ctx.get_or_add_id(egraph, &(&shl.operand1).into());
+            let id = egraph.add(Lang::Shl([op0, op1]));
+            ctx.to_id.insert((&shl.dest).into(), id);
+        }
         _ => todo!(),
     }
 }
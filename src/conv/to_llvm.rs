@@ -1,5 +1,10 @@
+use std::collections::HashMap;
+
+use crate::{Function, Lang};
+
 type Id = String;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum LLVMType {
     I64,
     I1,
@@ -21,8 +26,14 @@ struct LLVMBlock {
 
 enum LLVMInstr {
     Add((Id, LLVMType, LLVMValue, LLVMValue)),
+    Sub((Id, LLVMType, LLVMValue, LLVMValue)),
     Mul((Id, LLVMType, LLVMValue, LLVMValue)),
+    Shl((Id, LLVMType, LLVMValue, LLVMValue)),
+    And((Id, LLVMType, LLVMValue, LLVMValue)),
+    Or((Id, LLVMType, LLVMValue, LLVMValue)),
+    Xor((Id, LLVMType, LLVMValue, LLVMValue)),
     ICmp((Id, crate::lang::Cond, LLVMType, LLVMValue, LLVMValue)),
+    Select((Id, LLVMValue, LLVMType, LLVMValue, LLVMValue)),
 }
 
 enum LLVMTerm {
@@ -31,6 +42,7 @@ enum LLVMTerm {
     CBr((LLVMValue, Id, Id)),
 }
 
+#[derive(Debug, Clone)]
 enum LLVMValue {
     I64(i64),
     Bool(bool),
@@ -61,7 +73,12 @@ impl std::fmt::Display for LLVMInstr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             LLVMInstr::Add((dst, ty, lhs, rhs)) => write!(f, "\t%{dst} = add {ty} {lhs}, {rhs}"),
+            LLVMInstr::Sub((dst, ty, lhs, rhs)) => write!(f, "\t%{dst} = sub {ty} {lhs}, {rhs}"),
             LLVMInstr::Mul((dst, ty, lhs, rhs)) => write!(f, "\t%{dst} = mul {ty} {lhs}, {rhs}"),
+            LLVMInstr::Shl((dst, ty, lhs, rhs)) => write!(f, "\t%{dst} = shl {ty} {lhs}, {rhs}"),
+            LLVMInstr::And((dst, ty, lhs, rhs)) => write!(f, "\t%{dst} = and {ty} {lhs}, {rhs}"),
+            LLVMInstr::Or((dst, ty, lhs, rhs)) => write!(f, "\t%{dst} = or {ty} {lhs}, {rhs}"),
+            LLVMInstr::Xor((dst, ty, lhs, rhs)) => write!(f, "\t%{dst} = xor {ty} {lhs}, {rhs}"),
             LLVMInstr::ICmp((dst, cond, ty, lhs, rhs)) => {
                 use crate::lang::Cond;
                 let cond = match cond {
@@ -74,6 +91,9 @@ impl std::fmt::Display for LLVMInstr {
                 };
                 write!(f, "\t%{dst} = icmp {cond} {ty} {lhs}, {rhs}")
             }
+            LLVMInstr::Select((dst, cond, ty, t, e)) => {
+                write!(f, "\t%{dst} = select i1 {cond}, {ty} {t}, {ty} {e}")
+            }
         }
     }
 }
@@ -111,6 +131,120 @@ impl std::fmt::Display for LLVMFunction {
     }
 }
 
+/// LLVM IR of a function without loops and memory operations. Parameters are `i64`, and
+/// functions using one as `i1` are rejected. Phis become selects, which is sound since every
+/// remaining operation is total.
+pub fn emit(function: &Function) -> Result<String, String> {
+    let mut emitter = Emitter {
+        function,
+        values: HashMap::new(),
+        instrs: vec![],
+    };
+    let (ret_ty, ret) = emitter.value(function.root)?;
+    let function = LLVMFunction {
+        name: function.name.clone(),
+        params: function
+            .params
+            .iter()
+            .map(|param| (LLVMType::I64, param.clone()))
+            .collect(),
+        ret_ty,
+        entry: LLVMBlock {
+            instrs: emitter.instrs,
+            term: LLVMTerm::Ret((ret_ty, ret)),
+        },
+        blocks: vec![],
+    };
+    Ok(function.to_string())
+}
+
+type BinaryInstr = fn((Id, LLVMType, LLVMValue, LLVMValue)) -> LLVMInstr;
+
+struct Emitter<'a> {
+    function: &'a Function,
+    values: HashMap<egg::Id, (LLVMType, LLVMValue)>,
+    instrs: Vec<LLVMInstr>,
+}
+
+impl Emitter<'_> {
+    fn value(&mut self, id: egg::Id) -> Result<(LLVMType, LLVMValue), String> {
+        if let Some(value) = self.values.get(&id) {
+            return Ok(value.clone());
+        }
+        let dst = format!("v{}", usize::from(id));
+        let value = match &self.function.body[id] {
+            Lang::I64(i) => (LLVMType::I64, LLVMValue::I64(*i)),
+            Lang::I1(b) => (LLVMType::I1, LLVMValue::Bool(*b)),
+            Lang::Var(var) => (LLVMType::I64, LLVMValue::Id(var.to_string())),
+            Lang::Add([l, r]) => self.binary(dst, *l, *r, LLVMInstr::Add)?,
+            Lang::Sub([l, r]) => self.binary(dst, *l, *r, LLVMInstr::Sub)?,
+            Lang::Mul([l, r]) => self.binary(dst, *l, *r, LLVMInstr::Mul)?,
+            Lang::Shl([l, r]) => self.binary(dst, *l, *r, LLVMInstr::Shl)?,
+            Lang::BAnd([l, r]) | Lang::And([l, r]) => self.binary(dst, *l, *r, LLVMInstr::And)?,
+            Lang::BOr([l, r]) | Lang::Or([l, r]) => self.binary(dst, *l, *r, LLVMInstr::Or)?,
+            Lang::BNot(op) | Lang::Not(op) => {
+                let (ty, op) = self.value(*op)?;
+                let ones = match ty {
+                    LLVMType::I1 => LLVMValue::Bool(true),
+                    _ => LLVMValue::I64(-1),
+                };
+                self.instrs.push(LLVMInstr::Xor((dst.clone(), ty, op, ones)));
+                (ty, LLVMValue::Id(dst))
+            }
+            Lang::ICmp(cond, [l, r]) => {
+                let (ty, l) = self.value(*l)?;
+                let r = self.typed(*r, ty)?;
+                self.instrs.push(LLVMInstr::ICmp((dst.clone(), *cond, ty, l, r)));
+                (LLVMType::I1, LLVMValue::Id(dst))
+            }
+            Lang::Phi([c, t, e]) => {
+                let c = self.typed(*c, LLVMType::I1)?;
+                let (ty, t) = self.value(*t)?;
+                let e = self.typed(*e, ty)?;
+                self.instrs.push(LLVMInstr::Select((dst.clone(), c, ty, t, e)));
+                (ty, LLVMValue::Id(dst))
+            }
+            node @ (Lang::Alloca(_) | Lang::Ptr(_) | Lang::Load(_) | Lang::Store(_)) => {
+                return Err(format!(
+                    "cannot emit memory operation `{}` as LLVM IR",
+                    node
+                ))
+            }
+            node => return Err(format!("cannot emit `{}` as LLVM IR", node)),
+        };
+        self.values.insert(id, value.clone());
+        Ok(value)
+    }
+
+    fn binary(
+        &mut self,
+        dst: Id,
+        l: egg::Id,
+        r: egg::Id,
+        instr: BinaryInstr,
+    ) -> Result<(LLVMType, LLVMValue), String> {
+        let (ty, l) = self.value(l)?;
+        let r = self.typed(r, ty)?;
+        self.instrs.push(instr((dst.clone(), ty, l, r)));
+        Ok((ty, LLVMValue::Id(dst)))
+    }
+
+    /// Value of an operand that must have type `expected`
+    fn typed(&mut self, id: egg::Id, expected: LLVMType) -> Result<LLVMValue, String> {
+        let (ty, value) = self.value(id)?;
+        if ty == expected {
+            return Ok(value);
+        }
+        Err(match &self.function.body[id] {
+            Lang::Var(var) => format!(
+                "parameter `{}` is used as {}, but parameters are emitted as i64",
+                var, expected
+            ),
+            node => format!("`{}` has type {}, expected {}", node, ty, expected),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -130,4 +264,53 @@ mod tests {
 
         println!("{}", f);
     }
+
+    #[test]
+    fn emit_select() {
+        let body: egg::RecExpr<Lang> =
+            "(phi (< 0_v 1_v) (+ 0_v 1_v) (* 0_v 2_i64))".parse().unwrap();
+        let root = egg::Id::from(body.as_ref().len() - 1);
+        let function = Function {
+            name: "f".into(),
+            params: vec!["0".into(), "1".into()],
+            body,
+            root,
+        };
+        let ir = emit(&function).unwrap();
+        assert!(ir.starts_with("define i64 @f(i64 %0, i64 %1) {"));
+        assert!(ir.contains("= icmp slt i64 %0, %1"));
+        assert!(ir.contains("%v9 = select i1 %v2, i64 %v5, i64 %v8"));
+        assert!(ir.contains("ret i64 %v9"));
+    }
+
+    #[test]
+    fn boolean_parameter() {
+        let body: egg::RecExpr<Lang> = "(phi 0_v 1_v 2_v)".parse().unwrap();
+        let root = egg::Id::from(body.as_ref().len() - 1);
+        let function = Function {
+            name: "f".into(),
+            params: vec!["0".into(), "1".into(), "2".into()],
+            body,
+            root,
+        };
+        let err = emit(&function).unwrap_err();
+        assert_eq!(
+            err,
+            "parameter `0` is used as i1, but parameters are emitted as i64"
+        );
+    }
+
+    #[test]
+    fn memory_unsupported() {
+        let body: egg::RecExpr<Lang> = "(load alloca_0 (ptr alloca_0))".parse().unwrap();
+        let root = egg::Id::from(body.as_ref().len() - 1);
+        let function = Function {
+            name: "f".into(),
+            params: vec![],
+            body,
+            root,
+        };
+        let err = emit(&function).unwrap_err();
+        assert!(err.contains("memory operation `load`"), "{}", err);
+    }
 }
@@ -1,6 +1,14 @@
-use crate::Lang;
+use std::collections::HashMap;
+use std::path::Path;
 
-use egg::{CostFunction, Language};
+use crate::interp::BranchProfile;
+use crate::{EGraph, Lang};
+
+use egg::{CostFunction, Id, Language, RecExpr};
+use serde::{Deserialize, Serialize};
+
+/// Iterations assumed for every loop, as the trip count is not known statically
+pub const TRIP_COUNT: f64 = 10.0;
 
 pub struct NoAlloc;
 
@@ -11,34 +19,380 @@ impl CostFunction<Lang> for NoAlloc {
     where
         C: FnMut(egg::Id) -> Self::Cost,
     {
-        let (own_cost, multiplier) = match enode {
-            // Simple arithmetic operations should be cheap
-            Lang::Add(_) | Lang::Sub(_) => (1.0, 1.0),
-            // Multiplications are more expensive
-            Lang::Mul(_) => (4.0, 1.0),
-            // Bitwise operations are cheap
-            Lang::BAnd(_)
-            | Lang::BOr(_)
-            | Lang::BNot(_)
-            | Lang::And(_)
-            | Lang::Or(_)
-            | Lang::Not(_) => (0.5, 1.0),
-            // Comparisons are quite expensive
-            Lang::ICmp(..) => (3.0, 1.0),
-            // Memory operations are very expensive
-            Lang::Load(_) | Lang::Store(_) => (10.0, 1.0),
-            // Control flow is expensive, additional multiplier for inner nodes
-            Lang::Phi(_) => (10.0, 5.0),
-
-            // We don not want to allocate memory
-            Lang::Alloca(_) => (1000.0, 0.0),
-            // Constants are very cheap
-            Lang::Ptr(_) | Lang::I1(_) | Lang::I64(_) => (0.01, 0.0),
-            // "Variables" are cheap, but we want to avoid them if possible
-            Lang::Var(_) => (0.1, 0.0),
-            _ => todo!("Undefined cost for node {:?}", enode),
+        if let Lang::Theta([init, next]) = enode {
+            // The initial value is computed once, the next value in every iteration
+            return node_cost(enode) + costs(*init) + TRIP_COUNT * costs(*next);
+        }
+        let (own_cost, multiplier) = node_costs(enode);
+        own_cost + multiplier * enode.fold(0.0, |acc, id| acc + costs(id))
+    }
+}
+
+/// Cost of a node on its own, and the multiplier applied to the costs of its children
+fn node_costs(enode: &Lang) -> (f64, f64) {
+    match enode {
+        // Simple arithmetic operations should be cheap
+        Lang::Add(_) | Lang::Sub(_) | Lang::Shl(_) => (1.0, 1.0),
+        // Multiplications are more expensive
+        Lang::Mul(_) => (4.0, 1.0),
+        // Bitwise operations are cheap
+        Lang::BAnd(_)
+        | Lang::BOr(_)
+        | Lang::BNot(_)
+        | Lang::And(_)
+        | Lang::Or(_)
+        | Lang::Not(_) => (0.5, 1.0),
+        // Comparisons are quite expensive
+        Lang::ICmp(..) => (3.0, 1.0),
+        // Memory operations are very expensive
+        Lang::Load(_) | Lang::Store(_) => (10.0, 1.0),
+        // Control flow is expensive, additional multiplier for inner nodes
+        Lang::Phi(_) => (10.0, 5.0),
+        // A loop-carried value lives in a register updated in every iteration
+        Lang::Theta(_) => (TRIP_COUNT, 1.0),
+        // The exit condition is branched on in every iteration, the body is weighted by
+        // its thetas
+        Lang::Pass(_) => (3.0 * TRIP_COUNT, 1.0),
+        // Selecting the value at loop exit is a move
+        Lang::Eval(_) => (1.0, 1.0),
+
+        // We do not want to allocate memory
+        Lang::Alloca(_) => (1000.0, 0.0),
+        // Constants are very cheap
+        Lang::Ptr(_) | Lang::I1(_) | Lang::I64(_) => (0.01, 0.0),
+        // "Variables" are cheap, but we want to avoid them if possible
+        Lang::Var(_) => (0.1, 0.0),
+    }
+}
+
+/// Cost of a single node, for extractors that count every e-class once
+pub fn node_cost(enode: &Lang) -> f64 {
+    node_costs(enode).0
+}
+
+/// Cost function whose weights are read from a target description. Every field is the cost of
+/// a single node, loop nodes being charged once per iteration.
+///
+/// ```json
+/// { "add": 1.0, "mul": 4.0, "phi": 10.0, "phi_multiplier": 5.0, "trip_count": 10.0, ... }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CostModel {
+    pub add: f64,
+    pub sub: f64,
+    pub mul: f64,
+    pub shl: f64,
+    /// `&`, `|` and `~`
+    pub bitwise: f64,
+    /// `&&`, `||` and `!`
+    pub logic: f64,
+    pub icmp: f64,
+    pub load: f64,
+    pub store: f64,
+    pub alloca: f64,
+    /// Integer and boolean constants, and pointers
+    pub constant: f64,
+    pub var: f64,
+    /// Penalty of a branch
+    pub phi: f64,
+    /// Weight of the condition and both branches of a phi
+    pub phi_multiplier: f64,
+    /// Keeping a theta value up to date
+    pub loop_carried: f64,
+    /// Testing the exit condition of a loop
+    pub branch: f64,
+    /// Selecting the value of a loop at its exit
+    pub eval: f64,
+    /// Iterations assumed for every loop
+    pub trip_count: f64,
+}
+
+impl CostModel {
+    pub const PRESETS: [&'static str; 2] = ["size", "speed"];
+
+    /// Built-in models: `size` counts instructions, `speed` estimates latencies
+    pub fn preset(name: &str) -> Option<Self> {
+        let src = match name {
+            "size" => include_str!("../cost_models/size.json"),
+            "speed" => include_str!("../cost_models/speed.json"),
+            _ => return None,
         };
+        Some(Self::from_json(src).expect("preset is valid"))
+    }
 
-        own_cost + multiplier * enode.fold(0.0, |acc, id| acc + costs(id))
+    pub fn from_json(src: &str) -> Result<Self, String> {
+        serde_json::from_str(src).map_err(|err| format!("invalid cost model: {}", err))
+    }
+
+    /// Loads a preset by name, or a model from a file
+    pub fn load(name_or_path: &str) -> Result<Self, String> {
+        if let Some(model) = Self::preset(name_or_path) {
+            return Ok(model);
+        }
+        let path = Path::new(name_or_path);
+        let src = std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+        Self::from_json(&src)
+    }
+
+    /// Cost of a single node, for extractors that count every e-class once
+    pub fn node_cost(&self, enode: &Lang) -> f64 {
+        match enode {
+            Lang::Add(_) => self.add,
+            Lang::Sub(_) => self.sub,
+            Lang::Mul(_) => self.mul,
+            Lang::Shl(_) => self.shl,
+            Lang::BAnd(_) | Lang::BOr(_) | Lang::BNot(_) => self.bitwise,
+            Lang::And(_) | Lang::Or(_) | Lang::Not(_) => self.logic,
+            Lang::ICmp(..) => self.icmp,
+            Lang::Load(_) => self.load,
+            Lang::Store(_) => self.store,
+            Lang::Phi(_) => self.phi,
+            Lang::Theta(_) => self.loop_carried * self.trip_count,
+            Lang::Pass(_) => self.branch * self.trip_count,
+            Lang::Eval(_) => self.eval,
+            Lang::Alloca(_) => self.alloca,
+            Lang::Ptr(_) | Lang::I1(_) | Lang::I64(_) => self.constant,
+            Lang::Var(_) => self.var,
+        }
+    }
+}
+
+impl CostFunction<Lang> for CostModel {
+    type Cost = f64;
+
+    fn cost<C>(&mut self, enode: &Lang, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(egg::Id) -> Self::Cost,
+    {
+        let children = match enode {
+            // The initial value is computed once, the next value in every iteration
+            Lang::Theta([init, next]) => costs(*init) + self.trip_count * costs(*next),
+            Lang::Phi(_) => self.phi_multiplier * enode.fold(0.0, |acc, id| acc + costs(id)),
+            // The allocation of a pointer is paid for by the memory operations using it
+            Lang::Ptr(_) => 0.0,
+            _ => enode.fold(0.0, |acc, id| acc + costs(id)),
+        };
+        self.node_cost(enode) + children
+    }
+}
+
+/// [`CostModel`] weighting the arms of a phi by how often its condition was true in a
+/// [`BranchProfile`] of the input expression, so that a condition true half of the time costs
+/// as much as an unprofiled one. Phis whose condition, or its negation, was not profiled are
+/// charged by the model.
+pub struct ProfiledPhiCost<'a> {
+    egraph: &'a EGraph,
+    model: CostModel,
+    /// Probability of every profiled condition class being true
+    probabilities: HashMap<Id, f64>,
+}
+
+impl<'a> ProfiledPhiCost<'a> {
+    /// `expr` is the expression that was profiled, which must be represented in the e-graph
+    pub fn new(
+        egraph: &'a EGraph,
+        model: CostModel,
+        expr: &RecExpr<Lang>,
+        profile: &BranchProfile,
+    ) -> Self {
+        let mut probabilities = HashMap::new();
+        let mut ids: Vec<Option<Id>> = vec![];
+        for (i, node) in expr.as_ref().iter().enumerate() {
+            let children: Option<Vec<Id>> =
+                node.children().iter().map(|&child| ids[usize::from(child)]).collect();
+            let id = children.and_then(|children| {
+                let mut children = children.into_iter();
+                egraph.lookup(node.clone().map_children(|_| children.next().unwrap()))
+            });
+            if let (Some(id), Some(p)) = (id, profile.probability(i)) {
+                probabilities.insert(egraph.find(id), p);
+            }
+            ids.push(id);
+        }
+        Self {
+            egraph,
+            model,
+            probabilities,
+        }
+    }
+
+    fn probability(&self, cond: Id) -> Option<f64> {
+        let cond = self.egraph.find(cond);
+        if let Some(&p) = self.probabilities.get(&cond) {
+            return Some(p);
+        }
+        self.egraph[cond].nodes.iter().find_map(|node| match node {
+            Lang::Not(inner) => {
+                let p = self.probabilities.get(&self.egraph.find(*inner))?;
+                Some(1.0 - p)
+            }
+            _ => None,
+        })
+    }
+}
+
+impl CostFunction<Lang> for ProfiledPhiCost<'_> {
+    type Cost = f64;
+
+    fn cost<C>(&mut self, enode: &Lang, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        match enode {
+            Lang::Phi([cond, t, e]) => match self.probability(*cond) {
+                Some(p) => {
+                    let arms = 2.0 * (p * costs(*t) + (1.0 - p) * costs(*e));
+                    self.model.phi + self.model.phi_multiplier * (costs(*cond) + arms)
+                }
+                None => self.model.cost(enode, costs),
+            },
+            _ => self.model.cost(enode, costs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egg::RecExpr;
+
+    fn cost(expr: &str) -> f64 {
+        let expr: RecExpr<Lang> = expr.parse().unwrap();
+        NoAlloc.cost_rec(&expr)
+    }
+
+    #[test]
+    fn hoisting_is_cheaper() {
+        let in_loop = cost("(theta (+ 0_i64 x_v) (+ y_v (* x_v x_v)))");
+        let hoisted = cost("(+ (theta 0_i64 y_v) (* x_v x_v))");
+        assert!(hoisted < in_loop);
+    }
+
+    #[test]
+    fn presets() {
+        for name in CostModel::PRESETS {
+            assert!(CostModel::preset(name).is_some());
+        }
+        // The speed preset has the weights of `NoAlloc`
+        let expr: RecExpr<Lang> =
+            "(phi (< a_v 0_i64) (* a_v (load alloca_0 (ptr alloca_0))) 3_i64)"
+                .parse()
+                .unwrap();
+        let speed = CostModel::preset("speed").unwrap().cost_rec(&expr);
+        assert!((speed - NoAlloc.cost_rec(&expr)).abs() < 1e-9);
+        let size = CostModel::preset("size").unwrap().cost_rec(&expr);
+        assert!(size < speed);
+    }
+
+    #[test]
+    fn invalid_model() {
+        assert!(CostModel::from_json(r#"{ "add": 1.0 }"#).is_err());
+        let mut json = serde_json::to_value(CostModel::preset("size").unwrap()).unwrap();
+        json["div"] = 1.0.into();
+        assert!(CostModel::from_json(&json.to_string()).is_err());
+    }
+
+    /// Phi taking an expensive arm when `x` is negative
+    const GUARDED: &str =
+        "(phi (< x_v 0_i64) (* (* (* x_v x_v) (* x_v x_v)) (* (* x_v x_v) (* x_v x_v))) x_v)";
+    /// Values of `x` taking the expensive arm of [`GUARDED`] once and nine times out of ten
+    const RARE: [i64; 10] = [-1, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+    const COMMON: [i64; 10] = [-9, -8, -7, -6, -5, -4, -3, -2, -1, 1];
+
+    /// E-graph with [`GUARDED`] and the alternatives in its class
+    fn with_alternatives(alternatives: &[String]) -> (EGraph, Id) {
+        let mut egraph = EGraph::default();
+        let root = egraph.add_expr(&GUARDED.parse().unwrap());
+        for alternative in alternatives {
+            let other = egraph.add_expr(&alternative.parse().unwrap());
+            egraph.union(root, other);
+        }
+        egraph.rebuild();
+        (egraph, root)
+    }
+
+    /// Cheapest term under the speed model profiled on [`GUARDED`] with the values of `x`
+    fn extract_profiled(egraph: &EGraph, root: Id, xs: &[i64]) -> (f64, RecExpr<Lang>) {
+        use crate::interp::{Env, Expr, Value};
+
+        let guarded: RecExpr<Lang> = GUARDED.parse().unwrap();
+        let inputs: Vec<_> = xs
+            .iter()
+            .map(|&x| {
+                let mut env = Env::default();
+                env.set("x".into(), Value::I64(x));
+                env
+            })
+            .collect();
+        let profile = Expr::new(&guarded).profile(&inputs).unwrap();
+        let model = CostModel::preset("speed").unwrap();
+        let cost_fn = ProfiledPhiCost::new(egraph, model, &guarded, &profile);
+        egg::Extractor::new(egraph, cost_fn).find_best(root)
+    }
+
+    #[test]
+    fn profiled_phi() {
+        let product = (0..30).fold("x_v".to_string(), |product, _| {
+            format!("(* y_v {})", product)
+        });
+        let (egraph, root) = with_alternatives(&[product]);
+        // The expensive arm is rarely taken, so the phi is cheaper on average
+        let (_, rare) = extract_profiled(&egraph, root, &RARE);
+        assert!(matches!(rare.as_ref().last(), Some(Lang::Phi(_))));
+        let (_, common) = extract_profiled(&egraph, root, &COMMON);
+        assert!(matches!(common.as_ref().last(), Some(Lang::Mul(_))));
+    }
+
+    #[test]
+    fn profiled_against_unprofiled_phi() {
+        let (egraph, root) = with_alternatives(&[]);
+        let unprofiled = egg::Extractor::new(&egraph, CostModel::preset("speed").unwrap())
+            .find_best(root)
+            .0;
+        let (balanced, _) = extract_profiled(&egraph, root, &[-1, 1]);
+        assert!((balanced - unprofiled).abs() < 1e-9);
+
+        // The same phi with an equivalent condition that was not profiled
+        let flipped = format!(
+            "(phi (>= x_v 0_i64) x_v {})",
+            "(* (* (* x_v x_v) (* x_v x_v)) (* (* x_v x_v) (* x_v x_v)))"
+        );
+        let (egraph, root) = with_alternatives(&[flipped]);
+        let (_, rare) = extract_profiled(&egraph, root, &RARE);
+        assert!(rare.to_string().starts_with("(phi (< x_v"));
+        let (_, common) = extract_profiled(&egraph, root, &COMMON);
+        assert!(common.to_string().starts_with("(phi (>= x_v"));
+    }
+
+    /// PEG of `llvm_programs/double_loop`, with the loop-carried values `i`, `j`, the sum of
+    /// the outer loop `s` and of the inner loop `t` used in place of their theta nodes
+    fn double_loop(body: &str) -> String {
+        let inner = format!(
+            "(eval (theta s_v (+ t_v {})) (pass (! (< (theta 0_i64 (+ j_v 1_i64)) n_v))))",
+            body
+        );
+        format!(
+            "(eval (theta 0_i64 {}) (pass (! (< (theta 0_i64 (+ i_v 1_i64)) n_v))))",
+            inner
+        )
+    }
+
+    #[test]
+    fn nested_loops() {
+        // The body of the inner loop runs `TRIP_COUNT` times per iteration of the outer loop
+        let (body, larger) = ("(* i_v j_v)", "(+ (* i_v j_v) (* n_v 2_i64))");
+        let difference = cost(&double_loop(larger)) - cost(&double_loop(body));
+        let expected = TRIP_COUNT * TRIP_COUNT * (cost(larger) - cost(body));
+        assert!((difference - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn loop_nodes() {
+        let count = "(theta 0_i64 (+ i_v 1_i64))";
+        let exit = format!("(pass (>= {} n_v))", count);
+        let eval = cost(&format!("(eval {} {})", count, exit));
+        assert!(eval.is_finite() && eval > cost(count));
     }
 }
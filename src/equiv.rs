@@ -0,0 +1,183 @@
+//! Translation validation: two functions are saturated in one e-graph, with the parameters of
+//! the second one renamed to those of the first one by position. Before that, both are
+//! evaluated on small inputs to look for a counterexample.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use egg::{Id, Language, RecExpr, Symbol};
+
+use crate::explain::{explain, Proof};
+use crate::interp::{Env, Expr, Store, Value};
+use crate::optimizer::Optimizer;
+use crate::verify::assignments;
+use crate::{Function, Lang};
+
+/// Values parameters are instantiated with when looking for a counterexample
+const INPUTS: [i64; 8] = [-2, -1, 0, 1, 2, 3, 10, 100];
+
+#[derive(Debug, Clone)]
+pub enum Verdict {
+    /// Both roots ended up in the same e-class, as explained by the rewrites
+    Equivalent { proof: Proof },
+    /// Saturation stopped without merging the roots
+    NotProven,
+    /// Parameter values, named after the first function, on which the functions differ
+    Counterexample {
+        args: Vec<(String, Value)>,
+        left: Value,
+        right: Value,
+    },
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Equivalent { proof } => write!(f, "equivalent:\n{}", proof),
+            Verdict::NotProven => write!(f, "not proven"),
+            Verdict::Counterexample { args, left, right } => {
+                let args: Vec<_> = args
+                    .iter()
+                    .map(|(name, val)| format!("{} = {:?}", name, val))
+                    .collect();
+                write!(
+                    f,
+                    "counterexample: with {}, {:?} != {:?}",
+                    args.join(", "),
+                    left,
+                    right
+                )
+            }
+        }
+    }
+}
+
+/// Fails if the functions do not have the same number of parameters
+pub fn check_equivalence(
+    left: &Function,
+    right: &Function,
+    optimizer: &Optimizer,
+) -> Result<Verdict, String> {
+    if left.params.len() != right.params.len() {
+        return Err(format!(
+            "`{}` has {} parameters, but `{}` has {}",
+            left.name,
+            left.params.len(),
+            right.name,
+            right.params.len()
+        ));
+    }
+    let renaming: HashMap<Symbol, Symbol> = right
+        .params
+        .iter()
+        .zip(&left.params)
+        .map(|(r, l)| (Symbol::from(r), Symbol::from(l)))
+        .collect();
+    let left_expr = rooted(&left.body, left.root, &HashMap::new());
+    let right_expr = rooted(&right.body, right.root, &renaming);
+
+    if let Some(counterexample) = counterexample(&left.params, &left_expr, &right_expr) {
+        return Ok(counterexample);
+    }
+
+    let optimizer = optimizer.clone().with_explanations_enabled();
+    let mut egraph = optimizer.empty_egraph();
+    let roots = vec![egraph.add_expr(&left_expr), egraph.add_expr(&right_expr)];
+    let mut saturated = optimizer.run(egraph, roots);
+    if saturated.roots[0] != saturated.roots[1] {
+        return Ok(Verdict::NotProven);
+    }
+    let proof = explain(&mut saturated.egraph, &left_expr, &right_expr)?;
+    Ok(Verdict::Equivalent { proof })
+}
+
+/// Term of the function rooted at its return value, with variables renamed
+fn rooted(body: &RecExpr<Lang>, root: Id, renaming: &HashMap<Symbol, Symbol>) -> RecExpr<Lang> {
+    let rename = |node: &Lang| match node {
+        Lang::Var(var) => Lang::Var(*renaming.get(var).unwrap_or(var)),
+        node => node.clone(),
+    };
+    rename(&body[root]).build_recexpr(|id| rename(&body[id]))
+}
+
+fn counterexample(
+    params: &[String],
+    left: &RecExpr<Lang>,
+    right: &RecExpr<Lang>,
+) -> Option<Verdict> {
+    let interp = |expr, env| Expr::new(expr).interp(env, &mut Store::default());
+    assignments(params.len(), INPUTS.len())
+        .into_iter()
+        .find_map(|assignment| {
+            let args: Vec<_> = params
+                .iter()
+                .zip(assignment)
+                .map(|(param, i)| (param.clone(), Value::I64(INPUTS[i])))
+                .collect();
+            let mut env = Env::default();
+            for (param, val) in &args {
+                env.set(param.as_str().into(), *val);
+            }
+            match (interp(left, &env), interp(right, &env)) {
+                (Ok(left), Ok(right)) if left != right => {
+                    Some(Verdict::Counterexample { args, left, right })
+                }
+                _ => None,
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::Limits;
+
+    fn function(name: &str, params: &[&str], body: &str) -> Function {
+        let body: RecExpr<Lang> = body.parse().unwrap();
+        Function {
+            name: name.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            root: Id::from(body.as_ref().len() - 1),
+            body,
+        }
+    }
+
+    #[test]
+    fn equivalent() {
+        let left = function("f", &["x"], "(* (+ x_v 0_i64) 1_i64)");
+        let right = function("g", &["a"], "a_v");
+        let verdict = check_equivalence(&left, &right, &Optimizer::default()).unwrap();
+        let Verdict::Equivalent { proof } = verdict else {
+            panic!("expected equivalence, found {}", verdict);
+        };
+        assert!(proof.steps.iter().skip(1).all(|step| step.rule.is_some()));
+        assert_eq!(proof.steps.last().unwrap().term, "a_v");
+    }
+
+    #[test]
+    fn counterexample_found() {
+        let left = function("inc", &["x"], "(+ x_v 1_i64)");
+        let right = function("inc2", &["y"], "(+ y_v 2_i64)");
+        let verdict = check_equivalence(&left, &right, &Optimizer::default()).unwrap();
+        assert!(matches!(verdict, Verdict::Counterexample { .. }));
+    }
+
+    #[test]
+    fn not_proven() {
+        let left = function("f", &["x"], "(+ x_v 0_i64)");
+        let right = function("g", &["x"], "x_v");
+        let optimizer = Optimizer::default().with_limits(Limits {
+            iters: 0,
+            ..Limits::default()
+        });
+        let verdict = check_equivalence(&left, &right, &optimizer).unwrap();
+        assert!(matches!(verdict, Verdict::NotProven));
+    }
+
+    #[test]
+    fn parameter_mismatch() {
+        let left = function("f", &["x"], "x_v");
+        let right = function("g", &["x", "y"], "x_v");
+        assert!(check_equivalence(&left, &right, &Optimizer::default()).is_err());
+    }
+}
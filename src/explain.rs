@@ -0,0 +1,147 @@
+//! Rewrite proofs of why an extracted term is equal to the original one, as a sequence of
+//! terms in the `FromOp` syntax, each naming the rule that produced it.
+//!
+//! ```text
+//! (+ 0_i64 (* 1_i64 x_v))
+//!   = [zero addition] (* 1_i64 x_v)
+//!   = [one multiplication] x_v
+//! ```
+
+use egg::{FlatTerm, RecExpr};
+use serde::Serialize;
+
+use crate::consistency::flat_to_expr;
+use crate::{EGraph, Lang};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Step {
+    /// Rule rewriting the previous term into this one, `None` for the original term
+    pub rule: Option<String>,
+    /// The rule was applied from its right-hand side to its left-hand side
+    pub backward: bool,
+    pub term: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Proof {
+    pub steps: Vec<Step>,
+}
+
+/// Fails unless explanations are enabled and both terms are represented in the same e-class
+pub fn explain(
+    egraph: &mut EGraph,
+    original: &RecExpr<Lang>,
+    extracted: &RecExpr<Lang>,
+) -> Result<Proof, String> {
+    if !egraph.are_explanations_enabled() {
+        return Err("explanations are not enabled".to_string());
+    }
+    let class = |egraph: &EGraph, expr: &RecExpr<Lang>| {
+        let id = egraph
+            .lookup_expr(expr)
+            .ok_or_else(|| format!("{} is not in the e-graph", expr))?;
+        Ok::<_, String>(egraph.find(id))
+    };
+    if class(egraph, original)? != class(egraph, extracted)? {
+        return Err(format!("{} and {} are not equivalent", original, extracted));
+    }
+
+    let steps = egraph
+        .explain_equivalence(original, extracted)
+        .make_flat_explanation()
+        .iter()
+        .map(|term| {
+            let (rule, backward) = match applied_rewrite(term) {
+                Some((rule, backward)) => (Some(rule), backward),
+                None => (None, false),
+            };
+            Step {
+                rule,
+                backward,
+                term: flat_to_expr(term).to_string(),
+            }
+        })
+        .collect();
+    Ok(Proof { steps })
+}
+
+/// Name and direction of the rewrite applied somewhere in an explanation step
+fn applied_rewrite(term: &FlatTerm<Lang>) -> Option<(String, bool)> {
+    if let Some(rule) = term.forward_rule {
+        return Some((rule.to_string(), false));
+    }
+    if let Some(rule) = term.backward_rule {
+        return Some((rule.to_string(), true));
+    }
+    term.children.iter().find_map(applied_rewrite)
+}
+
+impl Proof {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("proof is serializable")
+    }
+}
+
+impl std::fmt::Display for Proof {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for step in &self.steps {
+            match &step.rule {
+                None => writeln!(f, "{}", step.term)?,
+                Some(rule) => {
+                    let direction = if step.backward { " (backward)" } else { "" };
+                    writeln!(f, "  = [{}{}] {}", rule, direction, step.term)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::Optimizer;
+    use egg::{AstSize, Extractor};
+
+    #[test]
+    fn steps_name_rules() {
+        let original: RecExpr<Lang> = "(+ 0_i64 (* 1_i64 x_v))".parse().unwrap();
+        let mut saturated = Optimizer::default()
+            .with_explanations_enabled()
+            .run_expr(&original);
+        let extractor = Extractor::new(&saturated.egraph, AstSize);
+        let (_, extracted) = extractor.find_best(saturated.roots[0]);
+        assert_eq!(extracted.to_string(), "x_v");
+
+        let proof = explain(&mut saturated.egraph, &original, &extracted).unwrap();
+        assert_eq!(proof.steps[0].rule, None);
+        assert_eq!(proof.steps[0].term, "(+ 0_i64 (* 1_i64 x_v))");
+        assert_eq!(proof.steps.last().unwrap().term, "x_v");
+        let rules: Vec<_> = proof.steps.iter().filter_map(|step| step.rule.as_deref()).collect();
+        assert!(rules.contains(&"zero addition"));
+        assert!(proof.to_string().contains("  = [zero addition]"));
+
+        let json: serde_json::Value = serde_json::from_str(&proof.to_json()).unwrap();
+        assert_eq!(json["steps"][0]["term"], "(+ 0_i64 (* 1_i64 x_v))");
+    }
+
+    #[test]
+    fn steps_name_analysis_merges() {
+        let original: RecExpr<Lang> = "(+ 1_i64 2_i64)".parse().unwrap();
+        let mut saturated = Optimizer::default()
+            .with_explanations_enabled()
+            .run_expr(&original);
+        let extracted = "3_i64".parse().unwrap();
+        let proof = explain(&mut saturated.egraph, &original, &extracted).unwrap();
+        let last = proof.steps.last().unwrap();
+        assert_eq!(last.rule.as_deref(), Some("constant folding"));
+    }
+
+    #[test]
+    fn requires_explanations() {
+        let original: RecExpr<Lang> = "(+ 0_i64 x_v)".parse().unwrap();
+        let mut saturated = Optimizer::default().run_expr(&original);
+        let extracted = "x_v".parse().unwrap();
+        assert!(explain(&mut saturated.egraph, &original, &extracted).is_err());
+    }
+}
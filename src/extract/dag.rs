@@ -0,0 +1,154 @@
+//! Greedy DAG extraction. Every e-class is assigned the node minimizing the cost of the
+//! sub-DAG below it, where the classes reachable through several children are counted once.
+//! This is a heuristic, since sharing with classes outside the sub-DAG is not taken into
+//! account.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use egg::{Id, Language, RecExpr};
+
+use crate::{EGraph, Lang};
+
+/// Passes over the e-graph before the current choices are used as they are
+const MAX_PASSES: usize = 100;
+
+/// Chosen node of a class, with the cost of every class in its sub-DAG
+struct Choice {
+    node: Lang,
+    classes: Rc<HashMap<Id, f64>>,
+    cost: f64,
+}
+
+pub struct DagExtractor<'a, F> {
+    egraph: &'a EGraph,
+    node_cost: F,
+    choices: HashMap<Id, Choice>,
+}
+
+impl<'a, F: Fn(&Lang) -> f64> DagExtractor<'a, F> {
+    pub fn new(egraph: &'a EGraph, node_cost: F) -> Self {
+        let mut extractor = Self {
+            egraph,
+            node_cost,
+            choices: HashMap::new(),
+        };
+        extractor.find_choices();
+        extractor
+    }
+
+    /// Cost of the sub-DAG of the class and its expression, `None` if no finite expression
+    /// could be extracted
+    pub fn find_best(&self, eclass: Id) -> Option<(f64, RecExpr<Lang>)> {
+        let eclass = self.egraph.find(eclass);
+        let cost = self.choices.get(&eclass)?.cost;
+        let mut expr = RecExpr::default();
+        let mut added = HashMap::new();
+        let mut visiting = vec![];
+        self.build(eclass, &mut expr, &mut added, &mut visiting)?;
+        Some((cost, expr))
+    }
+
+    fn build(
+        &self,
+        eclass: Id,
+        expr: &mut RecExpr<Lang>,
+        added: &mut HashMap<Id, Id>,
+        visiting: &mut Vec<Id>,
+    ) -> Option<Id> {
+        if let Some(&id) = added.get(&eclass) {
+            return Some(id);
+        }
+        if visiting.contains(&eclass) {
+            return None;
+        }
+        visiting.push(eclass);
+        let mut node = self.choices.get(&eclass)?.node.clone();
+        for child in node.children_mut() {
+            *child = self.build(self.egraph.find(*child), expr, added, visiting)?;
+        }
+        visiting.pop();
+        let id = expr.add(node);
+        added.insert(eclass, id);
+        Some(id)
+    }
+
+    /// Recomputes the choice of every class until none changes. Choices are kept up to date
+    /// with those of their children, and a node is only chosen if its own class is not below
+    /// it, so the choices are acyclic once they are stable.
+    fn find_choices(&mut self) {
+        for _ in 0..MAX_PASSES {
+            let mut changed = false;
+            for class in self.egraph.classes() {
+                let best = class
+                    .nodes
+                    .iter()
+                    .filter_map(|node| self.candidate(class.id, node))
+                    .min_by(|a, b| a.cost.total_cmp(&b.cost));
+                let Some(best) = best else {
+                    continue;
+                };
+                let update = match self.choices.get(&class.id) {
+                    Some(current) => current.node != best.node || current.cost != best.cost,
+                    None => true,
+                };
+                if update {
+                    self.choices.insert(class.id, best);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    fn candidate(&self, eclass: Id, node: &Lang) -> Option<Choice> {
+        let mut classes = HashMap::new();
+        for &child in node.children() {
+            let child = self.choices.get(&self.egraph.find(child))?;
+            classes.extend(child.classes.iter().map(|(&id, &cost)| (id, cost)));
+        }
+        if classes.contains_key(&eclass) {
+            return None;
+        }
+        classes.insert(eclass, (self.node_cost)(node));
+        let cost = classes.values().sum();
+        Some(Choice {
+            node: node.clone(),
+            classes: Rc::new(classes),
+            cost,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost_fn::{node_cost, NoAlloc};
+    use crate::extract::dag_cost;
+
+    #[test]
+    fn shared_subterm_counted_once() {
+        let mut egraph = EGraph::default();
+        // Uses an expensive subterm twice, which tree extraction counts twice
+        let shared = egraph.add_expr(
+            &"(+ (<< (* (* a_v b_v) (* c_v d_v)) 1_i64) (<< (* (* a_v b_v) (* c_v d_v)) 2_i64))"
+                .parse()
+                .unwrap(),
+        );
+        let unshared = egraph.add_expr(
+            &"(* (* (* e_v f_v) (* g_v h_v)) (* i_v j_v))"
+                .parse()
+                .unwrap(),
+        );
+        egraph.union(shared, unshared);
+        egraph.rebuild();
+
+        let (_, tree) = egg::Extractor::new(&egraph, NoAlloc).find_best(shared);
+        let (cost, dag) = DagExtractor::new(&egraph, node_cost).find_best(shared).unwrap();
+        assert!(dag.as_ref().iter().any(|node| matches!(node, Lang::Shl(_))));
+        assert!((cost - dag_cost(&dag, node_cost)).abs() < 1e-9);
+        assert!(cost < dag_cost(&tree, node_cost));
+    }
+}
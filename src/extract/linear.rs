@@ -0,0 +1,241 @@
+//! Extracted terms have to be emitted as sequential code, where a store consumes the memory
+//! state it is made on. A memory state (the witness of an allocation or the result of a store)
+//! may therefore be consumed by at most one store on every execution path. Loads only read a
+//! state and can always be scheduled before the stores consuming it.
+
+use std::collections::HashMap;
+
+use egg::{CostFunction, Id, Language, RecExpr};
+
+use crate::bdd::{Atom, Bdd};
+use crate::extract::multi::{top_k_where, Candidate, Terms};
+use crate::{EGraph, Lang};
+
+/// Checks that no memory state of the term rooted at its last node is consumed by two stores
+/// that can both be evaluated. Two stores are exclusive if they are in different arms of phis,
+/// as decided using the BDDs of the phi conditions. Only syntactically equal subterms are the
+/// same, and nodes are reported by their index in the term.
+pub fn check_linear(expr: &RecExpr<Lang>) -> Result<(), String> {
+    let mut terms = Terms::default();
+    let mut linearity = Linearity::default();
+    let mut ids: Vec<Id> = vec![];
+    let mut first_index: HashMap<Id, usize> = HashMap::new();
+    for (i, node) in expr.as_ref().iter().enumerate() {
+        let term = terms.add(node.clone().map_children(|child| ids[usize::from(child)]));
+        first_index.entry(term).or_insert(i);
+        linearity.add(&terms, term);
+        ids.push(term);
+    }
+    match ids.last().and_then(|&root| linearity.conflict(root)) {
+        Some((state, a, b)) => Err(format!(
+            "memory state at node {} is consumed by the stores at nodes {} and {}",
+            first_index[&state], first_index[&a], first_index[&b]
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Cheapest term of the class that passes [`check_linear`]. Terms failing the check are pruned
+/// from every class during extraction, which keeps up to `k` terms per class. `None` if the
+/// bounds cut off every linear term.
+pub fn find_best_linear<CF: CostFunction<Lang>>(
+    egraph: &EGraph,
+    root: Id,
+    k: usize,
+    mut cost_fn: CF,
+) -> Option<Candidate<CF::Cost>> {
+    let mut linearity = Linearity::default();
+    let linear = |terms: &Terms, term: Id| {
+        linearity.add(terms, term);
+        linearity.conflict(term).is_none()
+    };
+    top_k_where(egraph, root, k, &mut cost_fn, linear)
+        .into_iter()
+        .next()
+}
+
+/// Stores of a term consuming every memory state, with the condition under which each store is
+/// evaluated when the term is, over-approximated where BDDs get too large
+type Consumers = HashMap<Id, Vec<(Id, Bdd)>>;
+
+/// Consumers of terms, computed from those of their children so that every term is only looked
+/// at once. Terms are identified by their id in [`Terms`].
+#[derive(Debug, Default)]
+struct Linearity {
+    consumers: HashMap<Id, Consumers>,
+    /// BDDs of the terms used as phi conditions
+    conditions: HashMap<Id, Option<Bdd>>,
+}
+
+impl Linearity {
+    /// Computes the consumers of the term, whose children must have been added before
+    fn add(&mut self, terms: &Terms, term: Id) {
+        if self.consumers.contains_key(&term) {
+            return;
+        }
+        let node = terms.node(term);
+        let guards = match node {
+            Lang::Phi([c, _, _]) => match self.condition(terms, *c) {
+                Some(cond) => vec![Bdd::True, cond.clone(), cond.not()],
+                None => vec![Bdd::True; 3],
+            },
+            _ => vec![Bdd::True; node.len()],
+        };
+        let mut consumers = Consumers::new();
+        for (child, guard) in node.children().iter().zip(guards) {
+            for (&state, stores) in self.consumers[child].iter() {
+                for (store, store_guard) in stores {
+                    let store_guard = store_guard
+                        .and(&guard)
+                        .unwrap_or_else(|| store_guard.clone());
+                    consume(&mut consumers, state, *store, store_guard);
+                }
+            }
+        }
+        if let Lang::Store([_, state, _]) = node {
+            consume(&mut consumers, *state, term, Bdd::True);
+        }
+        self.consumers.insert(term, consumers);
+    }
+
+    /// Memory state of the added term consumed by two stores that can both be evaluated
+    fn conflict(&self, term: Id) -> Option<(Id, Id, Id)> {
+        self.consumers[&term].iter().find_map(|(&state, stores)| {
+            stores.iter().enumerate().find_map(|(n, (a, a_guard))| {
+                stores[n + 1..].iter().find_map(|(b, b_guard)| {
+                    let exclusive = a_guard.and(b_guard) == Some(Bdd::False);
+                    (!exclusive).then_some((state, *a, *b))
+                })
+            })
+        })
+    }
+
+    /// BDD of a boolean term, `None` if it is not known or too large
+    fn condition(&mut self, terms: &Terms, term: Id) -> Option<Bdd> {
+        if let Some(bdd) = self.conditions.get(&term) {
+            return bdd.clone();
+        }
+        let mut condition = |term: &Id| self.condition(terms, *term);
+        let bdd = match terms.node(term) {
+            Lang::I1(b) => Some(Bdd::constant(*b)),
+            Lang::Var(v) => Some(Bdd::atom(Atom::Var(*v))),
+            Lang::And([l, r]) => condition(l).zip(condition(r)).and_then(|(l, r)| l.and(&r)),
+            Lang::Or([l, r]) => condition(l).zip(condition(r)).and_then(|(l, r)| l.or(&r)),
+            Lang::Not(op) => condition(op).map(|op| op.not()),
+            Lang::Phi([c, t, e]) => match (condition(c), condition(t), condition(e)) {
+                (Some(c), Some(t), Some(e)) => Bdd::ite(&c, &t, &e),
+                _ => None,
+            },
+            Lang::ICmp(cond, [l, r]) => Some(Bdd::comparison(*cond, *l, *r)),
+            _ => None,
+        };
+        self.conditions.insert(term, bdd.clone());
+        bdd
+    }
+}
+
+/// Records that `store` consumes `state` under `guard`, in addition to the conditions it was
+/// already known to be evaluated under
+fn consume(consumers: &mut Consumers, state: Id, store: Id, guard: Bdd) {
+    let stores = consumers.entry(state).or_default();
+    match stores.iter_mut().find(|(other, _)| *other == store) {
+        Some((_, existing)) => *existing = existing.or(&guard).unwrap_or(Bdd::True),
+        None => stores.push((store, guard)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egg::AstSize;
+
+    /// Two stores on the initial state of an allocation, with the loads of their values
+    fn two_stores(expr: &mut RecExpr<Lang>) -> (Id, Id) {
+        let witness = expr.add(Lang::Alloca(0));
+        let ptr = expr.add(Lang::Ptr(witness));
+        let (one, two) = (expr.add(Lang::I64(1)), expr.add(Lang::I64(2)));
+        let first = expr.add(Lang::Store([one, witness, ptr]));
+        let second = expr.add(Lang::Store([two, witness, ptr]));
+        let first = expr.add(Lang::Load([first, ptr]));
+        let second = expr.add(Lang::Load([second, ptr]));
+        (first, second)
+    }
+
+    #[test]
+    fn sequential_stores() {
+        let mut expr = RecExpr::default();
+        let witness = expr.add(Lang::Alloca(0));
+        let ptr = expr.add(Lang::Ptr(witness));
+        let (one, two) = (expr.add(Lang::I64(1)), expr.add(Lang::I64(2)));
+        let first = expr.add(Lang::Store([one, witness, ptr]));
+        let second = expr.add(Lang::Store([two, first, ptr]));
+        let old = expr.add(Lang::Load([first, ptr]));
+        let new = expr.add(Lang::Load([second, ptr]));
+        expr.add(Lang::Add([old, new]));
+        assert_eq!(check_linear(&expr), Ok(()));
+    }
+
+    #[test]
+    fn duplicated_state() {
+        let mut expr = RecExpr::default();
+        let (first, second) = two_stores(&mut expr);
+        expr.add(Lang::Add([first, second]));
+        assert!(check_linear(&expr).is_err());
+    }
+
+    #[test]
+    fn exclusive_branches() {
+        let mut expr = RecExpr::default();
+        let (first, second) = two_stores(&mut expr);
+        let x = expr.add(Lang::Var("x".into()));
+        let zero = expr.add(Lang::I64(0));
+        let cond = expr.add(Lang::ICmp(crate::lang::Cond::Lt, [x, zero]));
+        let phi = expr.add(Lang::Phi([cond, first, second]));
+        assert_eq!(check_linear(&expr), Ok(()));
+
+        // Both stores are needed when the condition is true
+        let sum = expr.add(Lang::Add([first, second]));
+        expr.add(Lang::Phi([cond, sum, phi]));
+        assert!(check_linear(&expr).is_err());
+    }
+
+    #[test]
+    fn equal_values() {
+        // The stores write the same value, but both consume the initial state
+        let expr: RecExpr<Lang> = "(+ (load (store 2_i64 alloca_0 (ptr alloca_0)) (ptr alloca_0)) \
+            (load (store (+ 1_i64 1_i64) alloca_0 (ptr alloca_0)) (ptr alloca_0)))"
+            .parse()
+            .unwrap();
+        assert!(check_linear(&expr).is_err());
+    }
+
+    #[test]
+    fn skips_nonlinear_terms() {
+        let mut egraph = EGraph::default();
+        let witness = egraph.add(Lang::Alloca(0));
+        let ptr = egraph.add(Lang::Ptr(witness));
+        let (one, two) = (egraph.add(Lang::I64(1)), egraph.add(Lang::I64(2)));
+        let first = egraph.add(Lang::Store([one, witness, ptr]));
+        let second = egraph.add(Lang::Store([two, witness, ptr]));
+        let after = egraph.add(Lang::Store([two, first, ptr]));
+        let old = egraph.add(Lang::Load([first, ptr]));
+        let (second, after) = (
+            egraph.add(Lang::Load([second, ptr])),
+            egraph.add(Lang::Load([after, ptr])),
+        );
+        let nonlinear = egraph.add(Lang::Add([old, second]));
+        let linear = egraph.add(Lang::Add([old, after]));
+        egraph.union(nonlinear, linear);
+        egraph.rebuild();
+
+        let (_, best) = egg::Extractor::new(&egraph, AstSize).find_best(linear);
+        assert!(check_linear(&best).is_err());
+        let expected = "(+ (load (store 1_i64 alloca_0 (ptr alloca_0)) (ptr alloca_0)) \
+            (load (store 2_i64 (store 1_i64 alloca_0 (ptr alloca_0)) (ptr alloca_0)) \
+            (ptr alloca_0)))";
+        // The nonlinear term is cheaper, but pruned before it takes the only place of the class
+        let best = find_best_linear(&egraph, linear, 1, AstSize).unwrap();
+        assert_eq!(best.expr.to_string(), expected);
+        assert_eq!(find_best_linear(&egraph, linear, 0, AstSize), None);
+    }
+}
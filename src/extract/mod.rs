@@ -0,0 +1,29 @@
+//! Extractors beyond `egg::Extractor`, which sums the costs of a tree and so counts shared
+//! subterms once per use.
+
+pub mod dag;
+pub mod linear;
+pub mod multi;
+
+use std::collections::HashMap;
+
+use egg::{Id, Language, RecExpr};
+
+use crate::Lang;
+
+/// Cost of the expression counting every distinct subterm once
+pub fn dag_cost(expr: &RecExpr<Lang>, node_cost: impl Fn(&Lang) -> f64) -> f64 {
+    let mut canonical: Vec<Id> = vec![];
+    let mut seen: HashMap<Lang, Id> = HashMap::new();
+    let mut cost = 0.0;
+    for node in expr.as_ref() {
+        let node = node.clone().map_children(|child| canonical[usize::from(child)]);
+        let next = Id::from(seen.len());
+        let id = *seen.entry(node.clone()).or_insert_with(|| {
+            cost += node_cost(&node);
+            next
+        });
+        canonical.push(id);
+    }
+    cost
+}
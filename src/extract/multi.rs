@@ -0,0 +1,309 @@
+//! Extraction of several alternative terms for a class: the `k` cheapest under one cost
+//! function, or the Pareto frontier under two. Every class keeps a bounded set of candidate
+//! terms built from the candidates of its children, so the results are exact only as long as
+//! the bounds do not cut off a child term needed by a better parent term.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use egg::{CostFunction, Id, Language, RecExpr};
+
+use crate::{EGraph, Lang};
+
+/// Updates of every class on average before the current candidates are used as they are
+const MAX_PASSES: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate<C> {
+    pub cost: C,
+    pub expr: RecExpr<Lang>,
+}
+
+/// Terms built during extraction. Equal terms are built once, so a term is identified by its
+/// id, and the children of a node are the ids of other terms.
+#[derive(Debug, Default)]
+pub(crate) struct Terms {
+    nodes: Vec<Lang>,
+    ids: HashMap<Lang, Id>,
+}
+
+impl Terms {
+    pub(crate) fn add(&mut self, node: Lang) -> Id {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let id = Id::from(self.nodes.len());
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    pub(crate) fn node(&self, id: Id) -> &Lang {
+        &self.nodes[usize::from(id)]
+    }
+
+    /// The term as an expression rooted at its last node, sharing equal subterms
+    pub(crate) fn expr(&self, root: Id) -> RecExpr<Lang> {
+        let mut reachable = HashSet::new();
+        let mut todo = vec![root];
+        while let Some(id) = todo.pop() {
+            if reachable.insert(id) {
+                todo.extend_from_slice(self.node(id).children());
+            }
+        }
+        // Children are built before their parents and have smaller ids
+        let mut reachable: Vec<Id> = reachable.into_iter().collect();
+        reachable.sort();
+        let mut expr = RecExpr::default();
+        let mut ids: HashMap<Id, Id> = HashMap::new();
+        for id in reachable {
+            let node = self.node(id).clone().map_children(|child| ids[&child]);
+            ids.insert(id, expr.add(node));
+        }
+        expr
+    }
+}
+
+/// Candidate term of a class during extraction
+#[derive(Debug, Clone)]
+struct Choice<C> {
+    cost: C,
+    term: Id,
+}
+
+/// Up to `k` distinct terms of the class, cheapest first
+pub fn top_k<CF: CostFunction<Lang>>(
+    egraph: &EGraph,
+    root: Id,
+    k: usize,
+    mut cost_fn: CF,
+) -> Vec<Candidate<CF::Cost>> {
+    top_k_where(egraph, root, k, &mut cost_fn, |_, _| true)
+}
+
+/// Like [`top_k`], only keeping the terms of every class that are accepted by `accept`, which
+/// is given every new term once
+pub(crate) fn top_k_where<CF: CostFunction<Lang>>(
+    egraph: &EGraph,
+    root: Id,
+    k: usize,
+    cost_fn: &mut CF,
+    accept: impl FnMut(&Terms, Id) -> bool,
+) -> Vec<Candidate<CF::Cost>> {
+    extract(
+        egraph,
+        root,
+        |node, costs| cost_fn.cost(node, |i| costs[usize::from(i)].clone()),
+        accept,
+        |mut candidates| {
+            candidates.sort_by(|a, b| a.cost.partial_cmp(&b.cost).unwrap_or(Ordering::Equal));
+            candidates.truncate(k);
+            candidates
+        },
+    )
+}
+
+/// Terms of the class not dominated under both cost functions, by increasing first cost. At
+/// most `limit` terms are kept per class.
+pub fn pareto<A: CostFunction<Lang>, B: CostFunction<Lang>>(
+    egraph: &EGraph,
+    root: Id,
+    limit: usize,
+    mut a: A,
+    mut b: B,
+) -> Vec<Candidate<(A::Cost, B::Cost)>> {
+    extract(
+        egraph,
+        root,
+        |node, costs| {
+            (
+                a.cost(node, |i| costs[usize::from(i)].0.clone()),
+                b.cost(node, |i| costs[usize::from(i)].1.clone()),
+            )
+        },
+        |_, _| true,
+        |mut candidates| {
+            candidates.sort_by(|x, y| {
+                let first = x.cost.0.partial_cmp(&y.cost.0);
+                let second = x.cost.1.partial_cmp(&y.cost.1);
+                first.unwrap_or(Ordering::Equal).then(second.unwrap_or(Ordering::Equal))
+            });
+            // Sorted by the first cost, a candidate is dominated unless it improves on the
+            // second cost of every candidate before it
+            let mut frontier: Vec<Choice<_>> = vec![];
+            for candidate in candidates {
+                if frontier.last().map_or(true, |last| candidate.cost.1 < last.cost.1) {
+                    frontier.push(candidate);
+                }
+            }
+            frontier.truncate(limit);
+            frontier
+        },
+    )
+}
+
+/// Computes the candidates of every class until none changes, `keep` selecting the
+/// candidates of a class among the previous ones and the terms built from its nodes that
+/// `accept` allows. A class is only computed again once the candidates of a child changed.
+/// `cost` is given a node whose children are the positions of their costs.
+fn extract<C: Clone>(
+    egraph: &EGraph,
+    root: Id,
+    mut cost: impl FnMut(&Lang, &[C]) -> C,
+    mut accept: impl FnMut(&Terms, Id) -> bool,
+    keep: impl Fn(Vec<Choice<C>>) -> Vec<Choice<C>>,
+) -> Vec<Candidate<C>> {
+    let mut parents: HashMap<Id, Vec<Id>> = HashMap::new();
+    for class in egraph.classes() {
+        for node in &class.nodes {
+            for &child in node.children() {
+                parents
+                    .entry(egraph.find(child))
+                    .or_default()
+                    .push(class.id);
+            }
+        }
+    }
+
+    let mut terms = Terms::default();
+    let mut sets: HashMap<Id, Vec<Choice<C>>> = HashMap::new();
+    // Terms of every class already considered, kept or not
+    let mut tried: HashMap<Id, HashSet<Id>> = HashMap::new();
+    let mut pending: VecDeque<Id> = egraph.classes().map(|class| class.id).collect();
+    let mut queued: HashSet<Id> = pending.iter().copied().collect();
+    let mut updates = MAX_PASSES * egraph.number_of_classes();
+    while let Some(class) = pending.pop_front() {
+        queued.remove(&class);
+        if updates == 0 {
+            break;
+        }
+        updates -= 1;
+
+        let old = sets.get(&class).cloned().unwrap_or_default();
+        let mut candidates = old.clone();
+        let class_tried = tried.entry(class).or_default();
+        for node in &egraph[class].nodes {
+            let children: Option<Vec<&[Choice<C>]>> = node
+                .children()
+                .iter()
+                .map(|&child| sets.get(&egraph.find(child)).map(Vec::as_slice))
+                .collect();
+            let Some(children) = children else {
+                continue;
+            };
+            let mut position = 0;
+            let positional = node.clone().map_children(|_| {
+                position += 1;
+                Id::from(position - 1)
+            });
+            for choice in choices(&children) {
+                let mut chosen = choice.iter().map(|child| child.term);
+                let term = terms.add(node.clone().map_children(|_| chosen.next().unwrap()));
+                if !class_tried.insert(term) || !accept(&terms, term) {
+                    continue;
+                }
+                let costs: Vec<C> = choice.iter().map(|child| child.cost.clone()).collect();
+                candidates.push(Choice {
+                    cost: cost(&positional, &costs),
+                    term,
+                });
+            }
+        }
+        let candidates = keep(candidates);
+        let unchanged = old.len() == candidates.len()
+            && old.iter().zip(&candidates).all(|(a, b)| a.term == b.term);
+        if !unchanged {
+            sets.insert(class, candidates);
+            for &parent in parents.get(&class).into_iter().flatten() {
+                let parent = egraph.find(parent);
+                if queued.insert(parent) {
+                    pending.push_back(parent);
+                }
+            }
+        }
+    }
+    sets.remove(&egraph.find(root))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|choice| Candidate {
+            cost: choice.cost,
+            expr: terms.expr(choice.term),
+        })
+        .collect()
+}
+
+/// Every combination of one candidate per child
+fn choices<'a, C>(children: &[&'a [Choice<C>]]) -> Vec<Vec<&'a Choice<C>>> {
+    children.iter().fold(vec![vec![]], |choices, candidates| {
+        choices
+            .iter()
+            .flat_map(|choice| {
+                candidates.iter().map(move |candidate| {
+                    let mut choice = choice.clone();
+                    choice.push(candidate);
+                    choice
+                })
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost_fn::NoAlloc;
+    use egg::AstSize;
+
+    fn egraph(exprs: &[&str]) -> (EGraph, Id) {
+        let mut egraph = EGraph::default();
+        let ids: Vec<_> = exprs
+            .iter()
+            .map(|expr| egraph.add_expr(&expr.parse().unwrap()))
+            .collect();
+        for &id in &ids[1..] {
+            egraph.union(ids[0], id);
+        }
+        egraph.rebuild();
+        (egraph, ids[0])
+    }
+
+    #[test]
+    fn cheapest_first() {
+        let (egraph, root) = egraph(&["(* x_v 2_i64)", "(<< x_v 1_i64)", "(+ x_v x_v)"]);
+        let best: Vec<_> = top_k(&egraph, root, 3, NoAlloc)
+            .into_iter()
+            .map(|candidate| candidate.expr.to_string())
+            .collect();
+        assert_eq!(best, ["(<< x_v 1_i64)", "(+ x_v x_v)", "(* x_v 2_i64)"]);
+        assert_eq!(top_k(&egraph, root, 2, NoAlloc).len(), 2);
+    }
+
+    #[test]
+    fn costs_of_terms() {
+        // Costs are summed from the costs of the child candidates
+        let (egraph, root) = egraph(&[
+            "(+ (* x_v 2_i64) (* x_v 2_i64))",
+            "(* (<< x_v 1_i64) 2_i64)",
+            "(<< (+ x_v x_v) 1_i64)",
+        ]);
+        let candidates = top_k(&egraph, root, 5, NoAlloc);
+        assert_eq!(candidates.len(), 3);
+        for candidate in candidates {
+            assert!((candidate.cost - NoAlloc.cost_rec(&candidate.expr)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn frontier() {
+        let (egraph, root) = egraph(&[
+            "(* x_v 3_i64)",
+            "(+ (<< x_v 1_i64) x_v)",
+            "(+ (+ x_v x_v) x_v)",
+        ]);
+        let frontier: Vec<_> = pareto(&egraph, root, 10, AstSize, NoAlloc)
+            .into_iter()
+            .map(|candidate| candidate.expr.to_string())
+            .collect();
+        assert_eq!(frontier, ["(* x_v 3_i64)", "(+ (<< x_v 1_i64) x_v)"]);
+    }
+}
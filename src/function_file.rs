@@ -0,0 +1,204 @@
+//! Text format of whole functions, so that converted functions can be cached and checked in.
+//! The body lists every node in `RecExpr` order, bound to a name, so shared nodes stay shared
+//! and parsing a printed function gives it back unchanged:
+//!
+//! ```text
+//! function add(0, 1) {
+//!   let n0 = 0_v
+//!   let n1 = 1_v
+//!   let n2 = (+ n0 n1)
+//!   return n2
+//! }
+//! ```
+//!
+//! Leaves use the `FromOp` syntax of [`Lang`], other nodes list their operator followed by the
+//! names of their children, which must be bound before. Lines starting with `#` are comments.
+//! Parameter names must not contain `,`, `(`, `)` or whitespace.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use egg::{FromOp, Id, Language, RecExpr};
+
+use crate::{Function, Lang};
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "function {}({}) {{", self.name, self.params.join(", "))?;
+        for (i, node) in self.body.as_ref().iter().enumerate() {
+            if node.is_leaf() {
+                writeln!(f, "  let n{} = {}", i, node)?;
+            } else {
+                let children: Vec<_> =
+                    node.children().iter().map(|c| format!("n{}", c)).collect();
+                writeln!(f, "  let n{} = ({} {})", i, node, children.join(" "))?;
+            }
+        }
+        writeln!(f, "  return n{}", self.root)?;
+        write!(f, "}}")
+    }
+}
+
+impl FromStr for Function {
+    type Err = String;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        parse_function(src)
+    }
+}
+
+pub fn load_function(path: impl AsRef<Path>) -> Result<Function, String> {
+    let path = path.as_ref();
+    let src = std::fs::read_to_string(path)
+        .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+    src.parse()
+}
+
+pub fn save_function(path: impl AsRef<Path>, function: &Function) -> Result<(), String> {
+    let path = path.as_ref();
+    std::fs::write(path, format!("{}\n", function))
+        .map_err(|err| format!("cannot write {}: {}", path.display(), err))
+}
+
+fn parse_function(src: &str) -> Result<Function, String> {
+    let mut lines = src
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let (n, header) = lines.next().ok_or("expected a function")?;
+    let (name, params) = parse_header(header).map_err(|err| format!("line {}: {}", n, err))?;
+
+    let mut body = RecExpr::default();
+    let mut names: HashMap<&str, Id> = HashMap::new();
+    let mut root = None;
+    let mut closed = false;
+    for (n, line) in lines {
+        let at = |err: String| format!("line {}: {}", n, err);
+        if closed {
+            return Err(at("unexpected text after `}`".to_string()));
+        }
+        if line == "}" {
+            closed = true;
+        } else if let Some(name) = line.strip_prefix("return ") {
+            root = Some(lookup(&names, name.trim()).map_err(at)?);
+        } else {
+            let (name, node) = line
+                .strip_prefix("let ")
+                .and_then(|binding| binding.split_once(" = "))
+                .ok_or_else(|| at(format!("expected `let NAME = NODE`, found `{}`", line)))?;
+            let node = parse_node(node.trim(), &names).map_err(at)?;
+            let name = name.trim();
+            if names.insert(name, body.add(node)).is_some() {
+                return Err(at(format!("`{}` is bound twice", name)));
+            }
+        }
+    }
+    if !closed {
+        return Err("expected `}` at the end of the function".to_string());
+    }
+
+    Ok(Function {
+        name,
+        params,
+        body,
+        root: root.ok_or("expected `return NAME`")?,
+    })
+}
+
+/// `function NAME(PARAM, ...) {`
+fn parse_header(line: &str) -> Result<(String, Vec<String>), String> {
+    let expected = || format!("expected `function NAME(PARAMS) {{`, found `{}`", line);
+    let signature = line
+        .strip_prefix("function ")
+        .and_then(|rest| rest.strip_suffix('{'))
+        .ok_or_else(expected)?
+        .trim();
+    let (name, params) = signature
+        .strip_suffix(')')
+        .and_then(|signature| signature.split_once('('))
+        .ok_or_else(expected)?;
+    let params = params
+        .split(',')
+        .map(str::trim)
+        .filter(|param| !param.is_empty())
+        .map(str::to_string)
+        .collect();
+    Ok((name.trim().to_string(), params))
+}
+
+fn parse_node(src: &str, names: &HashMap<&str, Id>) -> Result<Lang, String> {
+    let (op, children) = match src.strip_prefix('(').and_then(|src| src.strip_suffix(')')) {
+        Some(inner) => {
+            let mut items = inner.split_whitespace();
+            let op = items.next().ok_or("expected an operator")?;
+            let children = items
+                .map(|name| lookup(names, name))
+                .collect::<Result<Vec<_>, _>>()?;
+            (op, children)
+        }
+        None => (src, vec![]),
+    };
+    Lang::from_op(op, children)
+}
+
+fn lookup(names: &HashMap<&str, Id>, name: &str) -> Result<Id, String> {
+    names
+        .get(name)
+        .copied()
+        .ok_or_else(|| format!("unbound name `{}`", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADD: &str = "\
+function add(0, 1) {
+  let n0 = 0_v
+  let n1 = 1_v
+  let n2 = (+ n0 n1)
+  return n2
+}";
+
+    #[test]
+    fn round_trip() {
+        let function: Function = ADD.parse().unwrap();
+        assert_eq!(function.params, ["0", "1"]);
+        assert_eq!(function.body.to_string(), "(+ 0_v 1_v)");
+        assert_eq!(function.to_string(), ADD);
+    }
+
+    #[test]
+    fn shared_nodes() {
+        // The root is not the last node, and the witness is used by both the store and the
+        // pointer
+        let mut body = RecExpr::default();
+        let witness = body.add(Lang::Alloca(3));
+        let ptr = body.add(Lang::Ptr(witness));
+        let zero = body.add(Lang::I64(0));
+        let store = body.add(Lang::Store([zero, witness, ptr]));
+        let load = body.add(Lang::Load([store, ptr]));
+        body.add(Lang::I1(true));
+        let function = Function {
+            name: "f".to_string(),
+            params: vec![],
+            body,
+            root: load,
+        };
+        assert_eq!(function.to_string().parse(), Ok(function));
+    }
+
+    #[test]
+    fn errors() {
+        let parse = |src: &str| src.parse::<Function>();
+        let unbound = ADD.replace("(+ n0 n1)", "(+ n0 n3)");
+        assert!(parse(&unbound).unwrap_err().contains("line 4"));
+        let arity = ADD.replace("(+ n0 n1)", "(+ n0)");
+        assert!(parse(&arity).is_err());
+        assert!(parse(ADD.trim_end_matches('}')).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::lang::{Cond, Lang};
 
 /// Value that can be a result of evaluating an expression
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Value {
     I64(i64),
     I1(bool),
@@ -23,23 +23,89 @@ impl Env {
     }
 }
 
-/// Map from (stack_slot, sigma) -> Value
+/// Number of times the condition of a phi was evaluated and was true, keyed by the index of
+/// the condition node in the expression
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BranchProfile(std::collections::HashMap<usize, (usize, usize)>);
+
+impl BranchProfile {
+    fn record(&mut self, cond: usize, taken: bool) {
+        let (total, true_count) = self.0.entry(cond).or_default();
+        *total += 1;
+        *true_count += usize::from(taken);
+    }
+
+    pub fn extend(&mut self, other: BranchProfile) {
+        for (cond, (total, true_count)) in other.0 {
+            let counts = self.0.entry(cond).or_default();
+            counts.0 += total;
+            counts.1 += true_count;
+        }
+    }
+
+    /// Fraction of the evaluations of the condition node that were true
+    pub fn probability(&self, cond: usize) -> Option<f64> {
+        let &(total, true_count) = self.0.get(&cond)?;
+        Some(true_count as f64 / total as f64)
+    }
+}
+
+/// Map from (stack_slot, sigma) -> Value, along with the sigma every store was made on. A
+/// slot not written at some sigma holds the value it had at the parent sigma. A profiling store
+/// also records the outcomes of phi conditions.
 #[derive(Debug, Clone, Default)]
-pub struct Store(std::collections::HashMap<(usize, usize), Option<Value>>);
+pub struct Store {
+    values: std::collections::HashMap<(usize, usize), Option<Value>>,
+    parents: std::collections::HashMap<usize, usize>,
+    profile: Option<BranchProfile>,
+}
 
 impl Store {
-    fn get(&self, stack_slot: usize, sigma: usize) -> Option<Value> {
-        self.0.get(&(stack_slot, sigma)).copied().flatten()
+    pub fn profiling() -> Self {
+        Self {
+            profile: Some(BranchProfile::default()),
+            ..Self::default()
+        }
+    }
+
+    pub fn into_profile(self) -> Option<BranchProfile> {
+        self.profile
+    }
+
+    fn get(&self, stack_slot: usize, mut sigma: usize) -> Option<Value> {
+        loop {
+            if let Some(val) = self.values.get(&(stack_slot, sigma)) {
+                return *val;
+            }
+            sigma = *self.parents.get(&sigma)?;
+        }
     }
 
     fn set(&mut self, stack_slot: usize, sigma: usize, val: Option<Value>) {
-        self.0.insert((stack_slot, sigma), val);
+        self.values.insert((stack_slot, sigma), val);
+    }
+
+    fn set_parent(&mut self, sigma: usize, parent: usize) {
+        self.parents.insert(sigma, parent);
+    }
+
+    fn record_branch(&mut self, cond: usize, taken: bool) {
+        if let Some(profile) = &mut self.profile {
+            profile.record(cond, taken);
+        }
     }
 }
 
+/// Iterations a `pass` node looks for its condition to hold in
+pub const MAX_ITERATIONS: usize = 1_000;
+
+/// Expression evaluated at some loop iteration. A `theta` is the sequence of values of a
+/// loop-carried variable: its initial value in iteration 0, and its next value evaluated in the
+/// previous iteration afterwards. Variables and other nodes are the same in every iteration.
 pub struct Expr<'a> {
     expr: &'a [Lang],
     root: usize,
+    iteration: usize,
 }
 
 impl<'a> Expr<'a> {
@@ -47,6 +113,7 @@ impl<'a> Expr<'a> {
         Self {
             expr: expr.as_ref(),
             root: expr.as_ref().len() - 1,
+            iteration: 0,
         }
     }
 
@@ -54,6 +121,16 @@ impl<'a> Expr<'a> {
         Self {
             expr: expr.as_ref(),
             root: usize::from(root),
+            iteration: 0,
+        }
+    }
+
+    /// Evaluates the expression in the given loop iteration instead of the first one
+    pub fn at_iteration(&self, iteration: usize) -> Self {
+        Self {
+            expr: self.expr,
+            root: self.root,
+            iteration,
         }
     }
 
@@ -61,6 +138,7 @@ impl<'a> Expr<'a> {
         Self {
             expr: self.expr,
             root,
+            iteration: self.iteration,
         }
     }
 
@@ -75,9 +153,22 @@ impl<'a> Expr<'a> {
             Lang::Var(v) => env
                 .get(v)
                 .ok_or_else(|| format!("unbound variable `{}`", v)),
-            Lang::Add([l, r]) => self.interp_binop(l, r, env, st, i64_binop(|l, r| l + r)),
-            Lang::Sub([l, r]) => self.interp_binop(l, r, env, st, i64_binop(|l, r| l - r)),
-            Lang::Mul([l, r]) => self.interp_binop(l, r, env, st, i64_binop(|l, r| l * r)),
+            Lang::Add([l, r]) => self.interp_binop(l, r, env, st, i64_binop(i64::wrapping_add)),
+            Lang::Sub([l, r]) => self.interp_binop(l, r, env, st, i64_binop(i64::wrapping_sub)),
+            Lang::Mul([l, r]) => self.interp_binop(l, r, env, st, i64_binop(i64::wrapping_mul)),
+            Lang::Shl([l, r]) => self.interp_binop(l, r, env, st, |l, r| match (l, r) {
+                (Value::I64(l), Value::I64(r @ 0..=63)) => Ok(Value::I64(l << r)),
+                _ => Err(format!("cannot shift {:?} by {:?}", l, r)),
+            }),
+            Lang::BAnd([l, r]) => self.interp_binop(l, r, env, st, i64_binop(|l, r| l & r)),
+            Lang::BOr([l, r]) => self.interp_binop(l, r, env, st, i64_binop(|l, r| l | r)),
+            Lang::BNot(op) => {
+                let op = self.reroot(usize::from(op)).interp(env, st)?;
+                match op {
+                    Value::I64(i) => Ok(Value::I64(!i)),
+                    _ => Err(format!("cannot apply `~` to {:?}", op)),
+                }
+            }
             Lang::ICmp(cond, [l, r]) => self.interp_cond(cond, l, r, env, st),
             Lang::And([l, r]) => self.interp_binop(l, r, env, st, i1_binop(|l, r| l && r)),
             Lang::Or([l, r]) => self.interp_binop(l, r, env, st, i1_binop(|l, r| l || r)),
@@ -89,12 +180,13 @@ impl<'a> Expr<'a> {
                 }
             }
             Lang::Phi([cnd, t, f]) => {
-                let cnd = self.reroot(usize::from(cnd)).interp(env, st)?;
-                match cnd {
-                    Value::I1(true) => self.reroot(usize::from(t)).interp(env, st),
-                    Value::I1(false) => self.reroot(usize::from(f)).interp(env, st),
-                    _ => Err(format!("cannot interp {:?}", cnd)),
-                }
+                let val = self.reroot(usize::from(cnd)).interp(env, st)?;
+                let Value::I1(taken) = val else {
+                    return Err(format!("cannot interp {:?}", val));
+                };
+                st.record_branch(usize::from(cnd), taken);
+                let arm = if taken { t } else { f };
+                self.reroot(usize::from(arm)).interp(env, st)
             }
             Lang::Alloca(_) => Ok(Value::Sigma(self.root)),
             Lang::Ptr(sig) => {
@@ -111,12 +203,13 @@ impl<'a> Expr<'a> {
                 let val = self.reroot(usize::from(val)).interp(env, st)?;
                 let sig = self.reroot(usize::from(sig)).interp(env, st)?;
                 let ptr = self.reroot(usize::from(ptr)).interp(env, st)?;
-                let Value::Sigma(_sig) = sig else {
+                let Value::Sigma(sig) = sig else {
                     return Err("Expected a sigma".to_string());
                 };
                 let Value::Ptr(ptr) = ptr else {
                     return Err("Expected a pointer".to_string());
                 };
+                st.set_parent(self.root, sig);
                 st.set(ptr, self.root, Some(val));
                 Ok(Value::Sigma(self.root))
             }
@@ -132,10 +225,51 @@ impl<'a> Expr<'a> {
                 st.get(ptr, sig)
                     .ok_or_else(|| "uninitialized value".to_string())
             }
-            _ => Err(format!("cannot interp {:?}", self.root())),
+            Lang::Theta([init, next]) => match self.iteration {
+                0 => self.reroot(usize::from(init)).interp(env, st),
+                i => {
+                    let next = self.reroot(usize::from(next)).at_iteration(i - 1);
+                    next.interp(env, st)
+                }
+            },
+            Lang::Eval([seq, i]) => {
+                let i = self.reroot(usize::from(i)).interp(env, st)?;
+                let iteration = match i {
+                    Value::I64(i) => usize::try_from(i).ok().filter(|&i| i < MAX_ITERATIONS),
+                    _ => None,
+                }
+                .ok_or_else(|| format!("cannot evaluate a loop in iteration {:?}", i))?;
+                let seq = self.reroot(usize::from(seq)).at_iteration(iteration);
+                seq.interp(env, st)
+            }
+            Lang::Pass(cond) => {
+                for i in 0..MAX_ITERATIONS {
+                    let cond = self.reroot(usize::from(cond)).at_iteration(i);
+                    match cond.interp(env, st)? {
+                        Value::I1(true) => return Ok(Value::I64(i as i64)),
+                        Value::I1(false) => {}
+                        val => return Err(format!("cannot pass {:?}", val)),
+                    }
+                }
+                Err(format!(
+                    "loop runs for more than {} iterations",
+                    MAX_ITERATIONS
+                ))
+            }
         }
     }
 
+    /// Evaluates the expression on every input with a profiling store
+    pub fn profile(&self, inputs: &[Env]) -> Result<BranchProfile, String> {
+        let mut profile = BranchProfile::default();
+        for env in inputs {
+            let mut st = Store::profiling();
+            self.interp(env, &mut st)?;
+            profile.extend(st.into_profile().unwrap_or_default());
+        }
+        Ok(profile)
+    }
+
     fn interp_binop(
         &self,
         l: egg::Id,
@@ -255,6 +389,25 @@ mod tests {
         assert_eq!(interp_empty(&expr), Ok(Value::I64(-1)));
     }
 
+    #[test]
+    fn mul_wraps() {
+        let mut expr = egg::RecExpr::default();
+        let n1 = expr.add(Lang::I64(i64::MAX));
+        let n2 = expr.add(Lang::I64(2));
+        expr.add(Lang::Mul([n1, n2]));
+        let expr = Expr::new(&expr);
+        assert_eq!(interp_empty(&expr), Ok(Value::I64(-2)));
+    }
+
+    #[test]
+    fn bitwise() {
+        let (mut expr, n1, n2) = two_and_three();
+        let and = expr.add(Lang::BAnd([n1, n2]));
+        expr.add(Lang::BNot(and));
+        let expr = Expr::new(&expr);
+        assert_eq!(interp_empty(&expr), Ok(Value::I64(!2)));
+    }
+
     #[test]
     fn compare_eq() {
         let (mut expr, n1, n2) = two_and_three();
@@ -301,6 +454,60 @@ mod tests {
         assert_eq!(interp_empty(&expr), Ok(Value::I64(5)));
     }
 
+    #[test]
+    fn branch_profile() {
+        let expr: egg::RecExpr<Lang> = "(phi (< x_v 0_i64) 1_i64 2_i64)".parse().unwrap();
+        let inputs: Vec<_> = [-1, 1, 2]
+            .into_iter()
+            .map(|x| Env([("x".into(), Value::I64(x))].into()))
+            .collect();
+        let profile = Expr::new(&expr).profile(&inputs).unwrap();
+        let Some(Lang::Phi([cond, ..])) = expr.as_ref().last() else {
+            unreachable!()
+        };
+        assert_eq!(profile.probability(usize::from(*cond)), Some(1.0 / 3.0));
+        assert_eq!(profile.probability(0), None);
+    }
+
+    #[test]
+    fn load_through_other_store() {
+        let mut expr = egg::RecExpr::default();
+        let (w0, w1) = (expr.add(Lang::Alloca(0)), expr.add(Lang::Alloca(1)));
+        let (p0, p1) = (expr.add(Lang::Ptr(w0)), expr.add(Lang::Ptr(w1)));
+        let (one, two) = (expr.add(Lang::I64(1)), expr.add(Lang::I64(2)));
+        let s1 = expr.add(Lang::Store([one, w1, p1]));
+        let s0 = expr.add(Lang::Store([two, s1, p0]));
+        let load = expr.add(Lang::Load([s0, p1]));
+        let uninit = expr.add(Lang::Load([s1, p0]));
+        assert_eq!(
+            interp_empty(&Expr::with_root(&expr, load)),
+            Ok(Value::I64(1))
+        );
+        assert!(interp_empty(&Expr::with_root(&expr, uninit)).is_err());
+    }
+
+    #[test]
+    fn loop_sequences() {
+        // The sequence 0, 5, 7, 7, ... first exceeds 4 in iteration 1
+        let expr: egg::RecExpr<Lang> =
+            "(eval (theta 10_i64 20_i64) (pass (> (theta 0_i64 (theta 5_i64 7_i64)) 4_i64)))"
+                .parse()
+                .unwrap();
+        assert_eq!(interp_empty(&Expr::new(&expr)), Ok(Value::I64(20)));
+
+        let expr: egg::RecExpr<Lang> = "(theta 1_i64 (* 2_i64 x_v))".parse().unwrap();
+        let env = Env([("x".into(), Value::I64(3))].into());
+        for (i, expected) in [1, 6, 6].into_iter().enumerate() {
+            let val = Expr::new(&expr)
+                .at_iteration(i)
+                .interp(&env, &mut Store::default());
+            assert_eq!(val, Ok(Value::I64(expected)));
+        }
+
+        let never: egg::RecExpr<Lang> = "(pass false)".parse().unwrap();
+        assert!(interp_empty(&Expr::new(&never)).is_err());
+    }
+
     #[test]
     fn env_variables() {
         let s1 = egg::Symbol::new("x");
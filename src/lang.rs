@@ -1,6 +1,5 @@
 use std::fmt::Display;
 use std::fmt::Formatter;
-use std::sync::atomic::AtomicU64;
 
 use egg::{FromOp, Id, Language, Symbol};
 
@@ -19,6 +18,7 @@ pub enum Lang {
     Add([Id; 2]),
     Sub([Id; 2]),
     Mul([Id; 2]),
+    Shl([Id; 2]),
 
     BAnd([Id; 2]),
     BOr([Id; 2]),
@@ -31,6 +31,7 @@ pub enum Lang {
 
     Phi([Id; 3]),
 
+    Theta([Id; 2]), // initial value, value in the next iteration
     Eval([Id; 2]), // sequence, nth of
     Pass(Id),      // returns index of first true in sequence
 
@@ -51,6 +52,7 @@ impl Language for Lang {
             (Add(_), Add(_))
             | (Sub(_), Sub(_))
             | (Mul(_), Mul(_))
+            | (Shl(_), Shl(_))
             | (BAnd(_), BAnd(_))
             | (BOr(_), BOr(_))
             | (BNot(_), BNot(_))
@@ -58,6 +60,7 @@ impl Language for Lang {
             | (Or(_), Or(_))
             | (Not(_), Not(_))
             | (Phi(_), Phi(_))
+            | (Theta(_), Theta(_))
             | (Eval(_), Eval(_))
             | (Pass(_), Pass(_))
             | (Ptr(_), Ptr(_))
@@ -77,6 +80,7 @@ impl Language for Lang {
             Lang::Add(ops) => ops,
             Lang::Sub(ops) => ops,
             Lang::Mul(ops) => ops,
+            Lang::Shl(ops) => ops,
             Lang::BAnd(ops) => ops,
             Lang::BOr(ops) => ops,
             Lang::BNot(op) => std::slice::from_ref(op),
@@ -87,6 +91,7 @@ impl Language for Lang {
 
             Lang::ICmp(_, ops) => ops,
             Lang::Phi(ops) => ops,
+            Lang::Theta(ops) => ops,
             Lang::Eval(ops) => ops,
             Lang::Pass(op) => std::slice::from_ref(op),
 
@@ -106,6 +111,7 @@ impl Language for Lang {
             Lang::Add(ops) => ops,
             Lang::Sub(ops) => ops,
             Lang::Mul(ops) => ops,
+            Lang::Shl(ops) => ops,
             Lang::BAnd(ops) => ops,
             Lang::BOr(ops) => ops,
             Lang::BNot(op) => std::slice::from_mut(op),
@@ -116,6 +122,7 @@ impl Language for Lang {
 
             Lang::ICmp(_, ops) => ops,
             Lang::Phi(ops) => ops,
+            Lang::Theta(ops) => ops,
             Lang::Eval(ops) => ops,
             Lang::Pass(op) => std::slice::from_mut(op),
 
@@ -131,15 +138,34 @@ impl Language for Lang {
     }
 }
 
-static CTR: AtomicU64 = AtomicU64::new(0);
-
 impl FromOp for Lang {
     type Error = String;
     fn from_op(op: &str, children: Vec<Id>) -> Result<Self, Self::Error> {
+        let arity = match op {
+            "true" | "false" => Some(0),
+            "~" | "!" | "pass" | "ptr" => Some(1),
+            "+" | "-" | "*" | "<<" | "&" | "|" | "&&" | "||" | "==" | "!=" | "<" | ">" | "<="
+            | ">=" | "theta" | "eval" | "load" => Some(2),
+            "phi" | "store" => Some(3),
+            // Other leaves are only known once they parse
+            _ => None,
+        };
+        let operands = |arity| {
+            format!(
+                "`{}` takes {} operands, found {}",
+                op,
+                arity,
+                children.len()
+            )
+        };
+        if let Some(arity) = arity.filter(|&arity| children.len() != arity) {
+            return Err(operands(arity));
+        }
         match op {
             "+" => Ok(Lang::Add([children[0], children[1]])),
             "-" => Ok(Lang::Sub([children[0], children[1]])),
             "*" => Ok(Lang::Mul([children[0], children[1]])),
+            "<<" => Ok(Lang::Shl([children[0], children[1]])),
             "&" => Ok(Lang::BAnd([children[0], children[1]])),
             "|" => Ok(Lang::BOr([children[0], children[1]])),
             "~" => Ok(Lang::BNot(children[0])),
@@ -153,33 +179,42 @@ impl FromOp for Lang {
             "<=" => Ok(Lang::ICmp(Cond::Leq, [children[0], children[1]])),
             ">=" => Ok(Lang::ICmp(Cond::Geq, [children[0], children[1]])),
             "phi" => Ok(Lang::Phi([children[0], children[1], children[2]])),
+            "theta" => Ok(Lang::Theta([children[0], children[1]])),
             "eval" => Ok(Lang::Eval([children[0], children[1]])),
             "pass" => Ok(Lang::Pass(children[0])),
-            "alloca" => {
-                let val = CTR.load(std::sync::atomic::Ordering::Relaxed);
-                CTR.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                Ok(Lang::Alloca(val))
-            }
             "ptr" => Ok(Lang::Ptr(children[0])),
             "load" => Ok(Lang::Load([children[0], children[1]])),
             "store" => Ok(Lang::Store([children[0], children[1], children[2]])),
             "true" => Ok(Lang::I1(true)),
             "false" => Ok(Lang::I1(false)),
-            other => {
-                let split: Vec<&str> = other.split_terminator('_').collect();
-                let [val, ty] = split.as_slice() else {
-                    Err(format!("Ill-formated value type: {}", op))?
-                };
-                match *ty {
-                    "i64" => Ok(Lang::I64(val.parse().unwrap())),
-                    "v" => Ok(Lang::Var(val.parse().unwrap())),
-                    _ => Err(format!("Unknown operator: {}", op))?,
-                }
-            }
+            other if !children.is_empty() => match parse_leaf(other) {
+                Ok(_) => Err(operands(0)),
+                Err(_) => Err(format!("Unknown operator: {}", op)),
+            },
+            other => parse_leaf(other),
         }
     }
 }
 
+fn parse_leaf(op: &str) -> Result<Lang, String> {
+    // `alloca_N` keeps the number it was printed with, so parsing is deterministic
+    if let Some(Ok(id)) = op.strip_prefix("alloca_").map(str::parse::<u64>) {
+        return Ok(Lang::Alloca(id));
+    }
+    // Split at the last `_`, variable names may contain underscores
+    let Some((val, ty)) = op.rsplit_once('_') else {
+        Err(format!("Ill-formated value type: {}", op))?
+    };
+    match ty {
+        "i64" => val
+            .parse()
+            .map(Lang::I64)
+            .map_err(|_| format!("Invalid integer: {}", op)),
+        "v" => Ok(Lang::Var(val.into())),
+        _ => Err(format!("Unknown operator: {}", op))?,
+    }
+}
+
 impl Display for Lang {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use Lang::*;
@@ -187,6 +222,7 @@ impl Display for Lang {
             Add(_) => write!(f, "+"),
             Sub(_) => write!(f, "-"),
             Mul(_) => write!(f, "*"),
+            Shl(_) => write!(f, "<<"),
             BAnd(_) => write!(f, "&"),
             BOr(_) => write!(f, "|"),
             BNot(_) => write!(f, "~"),
@@ -202,6 +238,7 @@ impl Display for Lang {
                 Cond::Geq => write!(f, ">="),
             },
             Phi(_) => write!(f, "phi"),
+            Theta(_) => write!(f, "theta"),
             Eval(_) => write!(f, "eval"),
             Pass(_) => write!(f, "pass"),
             Alloca(id) => write!(f, "alloca_{}", id),
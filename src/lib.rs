@@ -1,12 +1,27 @@
+pub mod analysis;
+pub mod bdd;
+pub mod conditions;
+pub mod consistency;
 pub mod conv;
 pub mod cost_fn;
+pub mod equiv;
+pub mod explain;
+pub mod function_file;
+pub mod extract;
 pub mod interp;
 pub mod lang;
+pub mod optimizer;
+pub mod report;
+pub mod rule_file;
 pub mod rules;
+pub mod synth;
+pub mod verify;
 
-pub type EGraph = egg::EGraph<lang::Lang, ()>;
+pub type EGraph = egg::EGraph<lang::Lang, analysis::LangAnalysis>;
+pub type Rewrite = egg::Rewrite<lang::Lang, analysis::LangAnalysis>;
 pub type Lang = lang::Lang;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Function {
     pub name: String,
     pub params: Vec<String>,
@@ -0,0 +1,315 @@
+//! Equality saturation in phases. Every phase runs its own rules on the e-graph left by the
+//! previous one, until it saturates or hits its limits:
+//!
+//! ```ignore
+//! let memory = RuleSelection::default().include_group("allocation");
+//! let optimizer = Optimizer::default()
+//!     .with_phase(Phase::from_selection("memory", &memory)?)
+//!     .with_phase(Phase::new("rest", rw_rules()));
+//! let saturated = optimizer.run_expr(&expr);
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+use egg::{
+    BackoffScheduler, Id, Iteration, Language, RecExpr, Runner, SimpleScheduler, StopReason,
+};
+
+use crate::analysis::LangAnalysis;
+use crate::report::{Recording, RuleStatsLog, SaturationReport};
+use crate::rules::{rw_rules, RuleSelection};
+use crate::{EGraph, Function, Lang, Rewrite};
+
+/// Bounds of a single phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub iters: usize,
+    pub nodes: usize,
+    pub time: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            iters: 100,
+            nodes: 100_000,
+            time: Duration::from_secs(15),
+        }
+    }
+}
+
+/// Which rules are searched in every iteration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchedulerConfig {
+    /// Every rule in every iteration
+    Simple,
+    /// egg's `BackoffScheduler`, banning rules that match too often. Rules missing from
+    /// `rule_ban_lengths` are banned for `ban_length` iterations.
+    Backoff {
+        match_limit: usize,
+        ban_length: usize,
+        rule_ban_lengths: HashMap<String, usize>,
+    },
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig::Backoff {
+            match_limit: 1_000,
+            ban_length: 5,
+            rule_ban_lengths: HashMap::new(),
+        }
+    }
+}
+
+/// Named set of rules run to a bound. Phases without limits use the limits of the optimizer.
+#[derive(Debug, Clone)]
+pub struct Phase {
+    pub name: String,
+    pub rules: Vec<Rewrite>,
+    pub limits: Option<Limits>,
+}
+
+impl Phase {
+    pub fn new(name: impl Into<String>, rules: Vec<Rewrite>) -> Self {
+        Self {
+            name: name.into(),
+            rules,
+            limits: None,
+        }
+    }
+
+    pub fn from_selection(
+        name: impl Into<String>,
+        selection: &RuleSelection,
+    ) -> Result<Self, String> {
+        Ok(Self::new(name, selection.select()?))
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Optimizer {
+    limits: Limits,
+    scheduler: SchedulerConfig,
+    phases: Vec<Phase>,
+    explanations: bool,
+}
+
+/// Outcome of a phase
+#[derive(Debug, Clone)]
+pub struct PhaseResult {
+    pub name: String,
+    pub stop_reason: StopReason,
+    pub iterations: Vec<Iteration<()>>,
+    pub rule_stats: RuleStatsLog,
+}
+
+pub struct Saturated {
+    pub egraph: EGraph,
+    pub roots: Vec<Id>,
+    pub phases: Vec<PhaseResult>,
+}
+
+impl Saturated {
+    pub fn report(&self) -> SaturationReport {
+        SaturationReport::new(&self.phases)
+    }
+}
+
+impl Optimizer {
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_scheduler(mut self, scheduler: SchedulerConfig) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Appends a phase. Without any phase, all built-in rules run in a single one.
+    pub fn with_phase(mut self, phase: Phase) -> Self {
+        self.phases.push(phase);
+        self
+    }
+
+    /// Records explanations, so that [`EGraph::explain_equivalence`] can be used on the result
+    pub fn with_explanations_enabled(mut self) -> Self {
+        self.explanations = true;
+        self
+    }
+
+    pub fn phases(&self) -> Vec<Phase> {
+        if self.phases.is_empty() {
+            vec![Phase::new("all", rw_rules())]
+        } else {
+            self.phases.clone()
+        }
+    }
+
+    pub fn run_expr(&self, expr: &RecExpr<Lang>) -> Saturated {
+        let mut egraph = self.empty_egraph();
+        let root = egraph.add_expr(expr);
+        self.run(egraph, vec![root])
+    }
+
+    /// Like [`Optimizer::run_expr`], for expressions whose root is not the last node, such as
+    /// the body of a [`Function`]
+    pub fn run_rooted(&self, expr: &RecExpr<Lang>, root: Id) -> Saturated {
+        let mut egraph = self.empty_egraph();
+        let mut ids: Vec<Id> = vec![];
+        for node in expr.as_ref() {
+            let node = node.clone().map_children(|child| ids[usize::from(child)]);
+            ids.push(egraph.add(node));
+        }
+        self.run(egraph, vec![ids[usize::from(root)]])
+    }
+
+    pub fn run_function(&self, function: &Function) -> Saturated {
+        self.run_rooted(&function.body, function.root)
+    }
+
+    /// E-graph to build the input in, with explanations enabled if requested
+    pub fn empty_egraph(&self) -> EGraph {
+        if self.explanations {
+            EGraph::default().with_explanations_enabled()
+        } else {
+            EGraph::default()
+        }
+    }
+
+    pub fn run(&self, mut egraph: EGraph, roots: Vec<Id>) -> Saturated {
+        egraph.rebuild();
+        let mut phases = vec![];
+        for phase in self.phases() {
+            let limits = phase.limits.unwrap_or(self.limits);
+            let log = Rc::new(RefCell::new(vec![]));
+            let runner = self
+                .runner(limits, log.clone())
+                .with_egraph(egraph)
+                .run(&phase.rules);
+            egraph = runner.egraph;
+            phases.push(PhaseResult {
+                name: phase.name,
+                stop_reason: runner.stop_reason.expect("runner has stopped"),
+                iterations: runner.iterations,
+                rule_stats: log.take(),
+            });
+        }
+        let roots = roots.into_iter().map(|root| egraph.find(root)).collect();
+        Saturated {
+            egraph,
+            roots,
+            phases,
+        }
+    }
+
+    fn runner(
+        &self,
+        limits: Limits,
+        log: Rc<RefCell<RuleStatsLog>>,
+    ) -> Runner<Lang, LangAnalysis> {
+        Runner::default()
+            .with_iter_limit(limits.iters)
+            .with_node_limit(limits.nodes)
+            .with_time_limit(limits.time)
+            .with_scheduler(self.scheduler(log))
+    }
+
+    /// Scheduler of a phase, recording the statistics of the rules into `log`
+    fn scheduler(&self, log: Rc<RefCell<RuleStatsLog>>) -> Recording {
+        match &self.scheduler {
+            SchedulerConfig::Simple => Recording::new(SimpleScheduler, log),
+            SchedulerConfig::Backoff {
+                match_limit,
+                ban_length,
+                rule_ban_lengths,
+            } => {
+                let scheduler = rule_ban_lengths.iter().fold(
+                    BackoffScheduler::default()
+                        .with_initial_match_limit(*match_limit)
+                        .with_ban_length(*ban_length),
+                    |scheduler, (rule, length)| scheduler.rule_ban_length(rule.as_str(), *length),
+                );
+                Recording::new(scheduler, log)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_run_in_order() {
+        let memory = RuleSelection::default().include_group("allocation");
+        let arithmetic = RuleSelection::default().include_group("arithmetic");
+        let limits = Limits {
+            iters: 5,
+            ..Limits::default()
+        };
+        let optimizer = Optimizer::default()
+            .with_scheduler(SchedulerConfig::Simple)
+            .with_phase(Phase::from_selection("memory", &memory).unwrap())
+            .with_phase(
+                Phase::from_selection("arithmetic", &arithmetic)
+                    .unwrap()
+                    .with_limits(limits),
+            );
+        let expr = "(+ 0_i64 (* 1_i64 x_v))".parse().unwrap();
+        let saturated = optimizer.run_expr(&expr);
+
+        let names: Vec<_> = saturated.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["memory", "arithmetic"]);
+        assert!(saturated.phases[1].iterations.len() <= 5);
+        let x = saturated.egraph.lookup_expr(&"x_v".parse().unwrap()).unwrap();
+        assert_eq!(saturated.egraph.find(x), saturated.roots[0]);
+    }
+
+    #[test]
+    fn rule_ban_lengths() {
+        use egg::RewriteScheduler;
+
+        let scheduler = SchedulerConfig::Backoff {
+            match_limit: 1,
+            ban_length: 1,
+            rule_ban_lengths: [("commutative addition".to_string(), 100)].into(),
+        };
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Optimizer::default()
+            .with_scheduler(scheduler)
+            .scheduler(log.clone());
+        let names = ["commutative addition", "commutative multiplication"];
+        let rules: Vec<_> = rw_rules()
+            .into_iter()
+            .filter(|rule| names.contains(&rule.name.as_str()))
+            .collect();
+        assert_eq!(rules.len(), names.len());
+
+        // Both rules match twice, which exceeds the limit and bans them in the first iteration
+        let mut egraph = EGraph::default();
+        egraph.add_expr(&"(+ (+ a_v b_v) (* (* c_v d_v) e_v))".parse().unwrap());
+        egraph.rebuild();
+        for iteration in 0..=100 {
+            for rule in &rules {
+                scheduler.search_rewrite(iteration, &egraph, rule);
+            }
+        }
+
+        let log = log.borrow();
+        let matches = |iteration: usize, rule: &str| log[iteration][rule].matches;
+        assert!((0..100).all(|iteration| matches(iteration, "commutative addition") == 0));
+        assert_eq!(matches(100, "commutative addition"), 2);
+        assert_eq!(matches(0, "commutative multiplication"), 0);
+        assert!((1..=100).all(|iteration| matches(iteration, "commutative multiplication") == 2));
+    }
+}
@@ -0,0 +1,200 @@
+//! Statistics of a saturation run: matches and applications of every rule in every iteration,
+//! along with the growth of the e-graph and the time spent.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use egg::{RewriteScheduler, SearchMatches};
+use serde::Serialize;
+
+use crate::analysis::LangAnalysis;
+use crate::optimizer::PhaseResult;
+use crate::{EGraph, Lang, Rewrite};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RuleStats {
+    /// Substitutions found by the searcher
+    pub matches: usize,
+    /// Applications that changed the e-graph
+    pub applications: usize,
+}
+
+impl std::ops::AddAssign for RuleStats {
+    fn add_assign(&mut self, other: Self) {
+        self.matches += other.matches;
+        self.applications += other.applications;
+    }
+}
+
+/// Statistics of every rule, per iteration
+pub type RuleStatsLog = Vec<BTreeMap<String, RuleStats>>;
+
+/// Scheduler recording the matches and applications of the rules scheduled by `inner`
+pub struct Recording {
+    inner: Box<dyn RewriteScheduler<Lang, LangAnalysis>>,
+    log: Rc<RefCell<RuleStatsLog>>,
+}
+
+impl Recording {
+    pub fn new(
+        inner: impl RewriteScheduler<Lang, LangAnalysis> + 'static,
+        log: Rc<RefCell<RuleStatsLog>>,
+    ) -> Self {
+        Self {
+            inner: Box::new(inner),
+            log,
+        }
+    }
+
+    fn stats(&self, iteration: usize, rule: &Rewrite) -> std::cell::RefMut<'_, RuleStats> {
+        std::cell::RefMut::map(self.log.borrow_mut(), |log| {
+            if log.len() <= iteration {
+                log.resize_with(iteration + 1, BTreeMap::new);
+            }
+            log[iteration].entry(rule.name.to_string()).or_default()
+        })
+    }
+}
+
+impl RewriteScheduler<Lang, LangAnalysis> for Recording {
+    fn can_stop(&mut self, iteration: usize) -> bool {
+        self.inner.can_stop(iteration)
+    }
+
+    fn search_rewrite<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph,
+        rewrite: &'a Rewrite,
+    ) -> Vec<SearchMatches<'a, Lang>> {
+        let matches = self.inner.search_rewrite(iteration, egraph, rewrite);
+        let found: usize = matches.iter().map(|m| m.substs.len()).sum();
+        self.stats(iteration, rewrite).matches += found;
+        matches
+    }
+
+    fn apply_rewrite(
+        &mut self,
+        iteration: usize,
+        egraph: &mut EGraph,
+        rewrite: &Rewrite,
+        matches: Vec<SearchMatches<Lang>>,
+    ) -> usize {
+        let applied = self.inner.apply_rewrite(iteration, egraph, rewrite, matches);
+        self.stats(iteration, rewrite).applications += applied;
+        applied
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SaturationReport {
+    pub phases: Vec<PhaseReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseReport {
+    pub name: String,
+    pub stop_reason: String,
+    pub iterations: Vec<IterationReport>,
+}
+
+/// Sizes are taken at the end of the iteration, times are in seconds
+#[derive(Debug, Clone, Serialize)]
+pub struct IterationReport {
+    pub egraph_nodes: usize,
+    pub egraph_classes: usize,
+    pub search_time: f64,
+    pub apply_time: f64,
+    pub rebuild_time: f64,
+    pub total_time: f64,
+    pub rules: BTreeMap<String, RuleStats>,
+}
+
+impl SaturationReport {
+    pub fn new(phases: &[PhaseResult]) -> Self {
+        let phases = phases
+            .iter()
+            .map(|phase| PhaseReport {
+                name: phase.name.clone(),
+                stop_reason: format!("{:?}", phase.stop_reason),
+                iterations: phase
+                    .iterations
+                    .iter()
+                    .enumerate()
+                    .map(|(i, iteration)| IterationReport {
+                        egraph_nodes: iteration.egraph_nodes,
+                        egraph_classes: iteration.egraph_classes,
+                        search_time: iteration.search_time,
+                        apply_time: iteration.apply_time,
+                        rebuild_time: iteration.rebuild_time,
+                        total_time: iteration.total_time,
+                        rules: phase.rule_stats.get(i).cloned().unwrap_or_default(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self { phases }
+    }
+
+    /// Statistics of every rule summed over all phases and iterations
+    pub fn rule_totals(&self) -> BTreeMap<String, RuleStats> {
+        let mut totals: BTreeMap<String, RuleStats> = BTreeMap::new();
+        let iterations = self.phases.iter().flat_map(|phase| &phase.iterations);
+        for (rule, stats) in iterations.flat_map(|iteration| &iteration.rules) {
+            *totals.entry(rule.clone()).or_default() += *stats;
+        }
+        totals
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report is serializable")
+    }
+}
+
+impl std::fmt::Display for SaturationReport {
+    /// Stop reasons and the rules with the most matches
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for phase in &self.phases {
+            let last = phase.iterations.last();
+            writeln!(
+                f,
+                "phase `{}`: {} iterations, {} nodes, {}",
+                phase.name,
+                phase.iterations.len(),
+                last.map_or(0, |iteration| iteration.egraph_nodes),
+                phase.stop_reason
+            )?;
+        }
+        let mut totals: Vec<_> = self.rule_totals().into_iter().collect();
+        totals.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.matches));
+        for (rule, stats) in totals.iter().take(10) {
+            writeln!(
+                f,
+                "  {:>8} matches {:>8} applications  {}",
+                stats.matches, stats.applications, rule
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimizer::Optimizer;
+
+    #[test]
+    fn counts_rules() {
+        let expr = "(+ 0_i64 (* 1_i64 x_v))".parse().unwrap();
+        let report = Optimizer::default().run_expr(&expr).report();
+
+        let totals = report.rule_totals();
+        let zero = totals["zero addition"];
+        assert!(zero.matches >= 1 && zero.applications >= 1);
+        assert!(totals.values().all(|stats| stats.applications <= stats.matches));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["phases"][0]["name"], "all");
+        assert!(json["phases"][0]["iterations"][0]["egraph_nodes"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,216 @@
+//! Rewrite rules loaded from text files, so that experiments do not need a recompilation.
+//!
+//! Every non-empty line that does not start with `#` is a rule:
+//!
+//! ```text
+//! # comment
+//! commutative addition: (+ ?a ?b) => (+ ?b ?a)
+//! not if: (phi ?c ?t ?e) <=> (phi (! ?c) ?e ?t)
+//! merge same: (phi ?c ?t ?e) => ?t if (equal ?t ?e)
+//! ```
+//!
+//! Patterns use the `FromOp` syntax of [`Lang`]. A bidirectional rule `name` produces the
+//! rewrites `name` and `name-rev`, both guarded by the conditions of the rule.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use egg::{ConditionalApplier, Pattern, Var};
+
+use crate::conditions::{self, Conditions, Predicate};
+use crate::{Lang, Rewrite};
+
+pub fn load_rules(path: impl AsRef<Path>) -> Result<Vec<Rewrite>, String> {
+    let path = path.as_ref();
+    let src = std::fs::read_to_string(path)
+        .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+    parse_rules(&src)
+}
+
+pub fn parse_rules(src: &str) -> Result<Vec<Rewrite>, String> {
+    let mut rules = vec![];
+    for (n, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = parse_rule(line).map_err(|err| format!("line {}: {}", n + 1, err))?;
+        rules.extend(parsed);
+    }
+    Ok(rules)
+}
+
+fn parse_rule(line: &str) -> Result<Vec<Rewrite>, String> {
+    let (name, body) = line
+        .split_once(':')
+        .ok_or_else(|| "expected `name: lhs => rhs`".to_string())?;
+    let name = name.trim();
+
+    let items = split_items(body)?;
+    let [lhs, arrow, rhs, conds @ ..] = items.as_slice() else {
+        return Err("expected `lhs => rhs` or `lhs <=> rhs`".to_string());
+    };
+    let lhs = parse_pattern(lhs)?;
+    let rhs = parse_pattern(rhs)?;
+    let conditions = parse_conditions(conds)?;
+
+    let rewrite = |name: String, lhs: &Pattern<Lang>, rhs: &Pattern<Lang>| {
+        let applier = ConditionalApplier {
+            condition: conditions.clone(),
+            applier: rhs.clone(),
+        };
+        Rewrite::new(name, lhs.clone(), applier)
+    };
+    match *arrow {
+        "=>" => Ok(vec![rewrite(name.to_string(), &lhs, &rhs)?]),
+        "<=>" => Ok(vec![
+            rewrite(name.to_string(), &lhs, &rhs)?,
+            rewrite(format!("{}-rev", name), &rhs, &lhs)?,
+        ]),
+        other => Err(format!("expected `=>` or `<=>`, found `{}`", other)),
+    }
+}
+
+fn parse_pattern(src: &str) -> Result<Pattern<Lang>, String> {
+    src.parse()
+        .map_err(|err| format!("invalid pattern `{}`: {}", src, err))
+}
+
+fn parse_conditions(items: &[&str]) -> Result<Conditions, String> {
+    let mut conditions = Conditions::default();
+    for pair in items.chunks(2) {
+        let [keyword, cond] = pair else {
+            return Err("expected a condition after `if`".to_string());
+        };
+        if *keyword != "if" {
+            return Err(format!("expected `if`, found `{}`", keyword));
+        }
+        let (predicate, vars) = parse_condition(cond)?;
+        conditions.push(predicate, vars);
+    }
+    Ok(conditions)
+}
+
+/// Parses a condition of the form `(name ?var ...)`
+fn parse_condition(src: &str) -> Result<(Arc<Predicate>, Vec<Var>), String> {
+    let inner = src
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| format!("expected `(condition ?var ...)`, found `{}`", src))?;
+    let mut words = inner.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let vars = words
+        .map(|var| {
+            var.parse::<Var>()
+                .map_err(|_| format!("expected a pattern variable, found `{}`", var))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let predicate = conditions::by_name(name, &vars).ok_or_else(|| {
+        format!(
+            "unknown condition `{}` with {} arguments",
+            name,
+            vars.len()
+        )
+    })?;
+    Ok((predicate, vars))
+}
+
+/// Splits a rule body into top-level atoms and parenthesized s-expressions
+fn split_items(src: &str) -> Result<Vec<&str>, String> {
+    let mut items = vec![];
+    let mut depth = 0usize;
+    let mut start = None;
+    for (i, c) in src.char_indices() {
+        match c {
+            '(' => {
+                start.get_or_insert(i);
+                depth += 1;
+            }
+            ')' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| "unbalanced parentheses".to_string())?;
+                if depth == 0 {
+                    items.push(&src[start.take().unwrap()..=i]);
+                }
+            }
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(start) = start.take() {
+                    items.push(&src[start..i]);
+                }
+            }
+            _ => {
+                start.get_or_insert(i);
+            }
+        }
+    }
+    if depth != 0 {
+        return Err("unbalanced parentheses".to_string());
+    }
+    if let Some(start) = start {
+        items.push(&src[start..]);
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{Optimizer, Phase};
+    use crate::EGraph;
+
+    #[test]
+    fn parse_directions() {
+        let rules = parse_rules(
+            "# comment\n\
+             commutative addition: (+ ?a ?b) => (+ ?b ?a)\n\
+             \n\
+             lt or eq: (|| (< ?a ?b) (== ?a ?b)) <=> (<= ?a ?b)\n",
+        )
+        .unwrap();
+        let names: Vec<_> = rules.iter().map(|r| r.name.to_string()).collect();
+        assert_eq!(names, ["commutative addition", "lt or eq", "lt or eq-rev"]);
+    }
+
+    #[test]
+    fn conditions() {
+        let rules = parse_rules("same: (phi ?c ?t ?e) => ?t if (equal ?t ?e)").unwrap();
+        let mut egraph = EGraph::default();
+        let expr = "(phi c_v (+ 1_i64 x_v) (+ 1_i64 x_v))".parse().unwrap();
+        let root = egraph.add_expr(&expr);
+        let other = egraph.add_expr(&"(phi c_v 1_i64 2_i64)".parse().unwrap());
+        let saturated = Optimizer::default()
+            .with_phase(Phase::new("file", rules))
+            .run(egraph, vec![]);
+
+        let one_x = saturated
+            .egraph
+            .lookup_expr(&"(+ 1_i64 x_v)".parse().unwrap())
+            .unwrap();
+        assert_eq!(saturated.egraph.find(root), saturated.egraph.find(one_x));
+        assert_eq!(saturated.egraph[other].nodes.len(), 1);
+    }
+
+    #[test]
+    fn analysis_conditions() {
+        let rules = parse_rules("shift: (* ?a ?c) => (* ?c ?a) if (pow2 ?c)").unwrap();
+        let mut egraph = EGraph::default();
+        let pow2 = egraph.add_expr(&"(* x_v 4_i64)".parse().unwrap());
+        let other = egraph.add_expr(&"(* x_v 6_i64)".parse().unwrap());
+        let saturated = Optimizer::default()
+            .with_phase(Phase::new("file", rules))
+            .run(egraph, vec![]);
+
+        assert_eq!(saturated.egraph[pow2].nodes.len(), 2);
+        assert_eq!(saturated.egraph[other].nodes.len(), 1);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_rules("no arrow: (+ ?a ?b)").is_err());
+        assert!(parse_rules("unbound: (+ ?a ?b) => ?c").is_err());
+        assert!(parse_rules("bad cond: ?a => ?a if (frobnicate ?a)").is_err());
+        assert!(parse_rules("unbalanced: (+ ?a ?b => ?a").is_err());
+    }
+}
@@ -1,30 +1,133 @@
+use crate::analysis::{Constant, LangAnalysis};
+use crate::conditions::{
+    distinct_pointers, is_const, is_invariant, is_nonzero, is_odd, is_power_of_two, var,
+};
 use crate::lang::Lang;
-use egg::{rewrite, Rewrite};
+use crate::{EGraph, Rewrite};
+use egg::{rewrite, Applier, ConditionalApplier, Id, Pattern, PatternAst, Subst, Symbol, Var};
 
-pub fn rw_rules() -> Vec<Rewrite<Lang, ()>> {
-    let mut rules = vec![];
-    rules.extend(allocation_rules());
-    rules.extend(arithmetic_rules());
-    rules.extend(phi_rules());
-    rules.extend(logic_rules());
-    rules.extend(cond_rules());
-    rules
+pub fn rw_rules() -> Vec<Rewrite> {
+    rule_groups()
+        .into_iter()
+        .flat_map(|(_, rules)| rules)
+        .collect()
+}
+
+/// All built-in rules, grouped by name
+pub fn rule_groups() -> Vec<(&'static str, Vec<Rewrite>)> {
+    vec![
+        ("allocation", allocation_rules()),
+        ("arithmetic", arithmetic_rules()),
+        ("phi", phi_rules()),
+        ("distribution", phi_distribution_rules()),
+        ("logic", logic_rules()),
+        ("cond", cond_rules()),
+        ("conditional", conditional_rules()),
+        ("loop", loop_rules()),
+    ]
+}
+
+/// Subset of the built-in rules. The selected groups (all of them if none are included
+/// explicitly) are taken without the excluded groups and rules, then the explicitly included
+/// rules are added. Bidirectional rules are selected per direction, the reverse direction
+/// being named `<name>-rev`.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSelection {
+    include_groups: Vec<String>,
+    exclude_groups: Vec<String>,
+    include_rules: Vec<String>,
+    exclude_rules: Vec<String>,
 }
 
-fn arithmetic_rules() -> Vec<Rewrite<Lang, ()>> {
+impl RuleSelection {
+    pub fn include_group(mut self, name: impl Into<String>) -> Self {
+        self.include_groups.push(name.into());
+        self
+    }
+
+    pub fn exclude_group(mut self, name: impl Into<String>) -> Self {
+        self.exclude_groups.push(name.into());
+        self
+    }
+
+    pub fn include_rule(mut self, name: impl Into<String>) -> Self {
+        self.include_rules.push(name.into());
+        self
+    }
+
+    pub fn exclude_rule(mut self, name: impl Into<String>) -> Self {
+        self.exclude_rules.push(name.into());
+        self
+    }
+
+    /// Fails if any of the given group or rule names is unknown
+    pub fn select(&self) -> Result<Vec<Rewrite>, String> {
+        let groups = rule_groups();
+        for group in self.include_groups.iter().chain(&self.exclude_groups) {
+            if !groups.iter().any(|(name, _)| name == group) {
+                return Err(format!("unknown rule group `{}`", group));
+            }
+        }
+        for rule in self.include_rules.iter().chain(&self.exclude_rules) {
+            if !groups
+                .iter()
+                .any(|(_, rules)| rules.iter().any(|r| r.name.as_str() == rule))
+            {
+                return Err(format!("unknown rule `{}`", rule));
+            }
+        }
+
+        let selected_group = |group: &str| {
+            (self.include_groups.is_empty() || self.include_groups.iter().any(|g| g == group))
+                && !self.exclude_groups.iter().any(|g| g == group)
+        };
+        let selected_rule = |group: &str, rule: &str| {
+            if self.include_rules.iter().any(|r| r == rule) {
+                return true;
+            }
+            selected_group(group) && !self.exclude_rules.iter().any(|r| r == rule)
+        };
+
+        Ok(groups
+            .into_iter()
+            .flat_map(|(group, rules)| {
+                rules
+                    .into_iter()
+                    .filter(move |rule| selected_rule(group, rule.name.as_str()))
+            })
+            .collect())
+    }
+}
+
+fn arithmetic_rules() -> Vec<Rewrite> {
     let mut unidirectional = vec![
         rewrite!("commutative addition"; "(+ ?a ?b)" => "(+ ?b ?a)"),
         rewrite!("commutative multiplication"; "(* ?a ?b)" => "(* ?b ?a)"),
         rewrite!("one multiplication"; "(* 1_i64 ?a)" => "?a"),
         rewrite!("zero multiplication"; "(* 0_i64 ?a)" => "0_i64"),
         rewrite!("zero addition"; "(+ 0_i64 ?a)" => "?a"),
+        rewrite!("zero subtraction"; "(- ?a 0_i64)" => "?a"),
+        rewrite!("self subtraction"; "(- ?a ?a)" => "0_i64"),
+        rewrite!("addition subtraction cancel"; "(- (+ ?a ?b) ?b)" => "?a"),
+        rewrite!("subtraction addition cancel"; "(+ (- ?a ?b) ?b)" => "?a"),
+        rewrite!("commutative bitwise and"; "(& ?a ?b)" => "(& ?b ?a)"),
+        rewrite!("commutative bitwise or"; "(| ?a ?b)" => "(| ?b ?a)"),
+        rewrite!("idempotent bitwise and"; "(& ?a ?a)" => "?a"),
+        rewrite!("idempotent bitwise or"; "(| ?a ?a)" => "?a"),
+        rewrite!("zero bitwise and"; "(& 0_i64 ?a)" => "0_i64"),
+        rewrite!("zero bitwise or"; "(| 0_i64 ?a)" => "?a"),
+        rewrite!("double bitwise not"; "(~ (~ ?a))" => "?a"),
+        rewrite!("zero shift"; "(<< ?a 0_i64)" => "?a"),
     ];
 
     let biderectional = vec![
         rewrite!("multiplication distribution over addition";
                  "(* (+ ?a ?b) ?m)" <=> "(+ (* ?a ?m) (* ?b ?m))"),
-        // rewrite!("loop multiplication"; "(* (theta ?init ?next) ?m)" <=> "(theta (* ?init ?m) (* ?next ?m))"),
-        // rewrite!("if multiplication"; "(* (phi ?c ?t ?e) ?m)" <=> "(phi ?c (* ?t ?m) (* ?e ?m))"),
+        rewrite!("multiplication distribution over subtraction";
+                 "(* (- ?a ?b) ?m)" <=> "(- (* ?a ?m) (* ?b ?m))"),
+        rewrite!("associative addition"; "(+ ?a (+ ?b ?c))" <=> "(+ (+ ?a ?b) ?c)"),
+        rewrite!("associative multiplication"; "(* ?a (* ?b ?c))" <=> "(* (* ?a ?b) ?c)"),
+        rewrite!("subtraction as addition"; "(- ?a ?b)" <=> "(+ ?a (* -1_i64 ?b))"),
     ]
     .concat();
 
@@ -32,19 +135,100 @@ fn arithmetic_rules() -> Vec<Rewrite<Lang, ()>> {
     unidirectional
 }
 
-fn allocation_rules() -> Vec<Rewrite<Lang, ()>> {
+/// Rules moving computations into and out of loops. Pointwise operations with a loop invariant
+/// operand are distributed over theta nodes, which turns a multiplication of an induction
+/// variable into an induction variable with a multiplied step, and are hoisted back out.
+fn loop_rules() -> Vec<Rewrite> {
+    let invariant = || is_invariant(var("?a"));
+    vec![
+        rewrite!("loop addition"; "(+ (theta ?i ?n) ?a)" => "(theta (+ ?i ?a) (+ ?n ?a))"
+                 if invariant()),
+        rewrite!("loop subtraction"; "(- (theta ?i ?n) ?a)" => "(theta (- ?i ?a) (- ?n ?a))"
+                 if invariant()),
+        rewrite!("loop multiplication"; "(* (theta ?i ?n) ?a)" => "(theta (* ?i ?a) (* ?n ?a))"
+                 if invariant()),
+        rewrite!("hoist addition"; "(theta (+ ?i ?a) (+ ?n ?a))" => "(+ (theta ?i ?n) ?a)"
+                 if invariant()),
+        rewrite!("hoist subtraction"; "(theta (- ?i ?a) (- ?n ?a))" => "(- (theta ?i ?n) ?a)"
+                 if invariant()),
+        rewrite!("hoist multiplication"; "(theta (* ?i ?a) (* ?n ?a))" => "(* (theta ?i ?n) ?a)"
+                 if invariant()),
+        rewrite!("invariant theta"; "(theta ?a ?a)" => "?a" if invariant()),
+        rewrite!("invariant eval"; "(eval ?a ?n)" => "?a" if invariant()),
+        rewrite!("eval addition"; "(eval (+ ?a ?b) ?n)" => "(+ (eval ?a ?n) (eval ?b ?n))"),
+        rewrite!("eval multiplication"; "(eval (* ?a ?b) ?n)" => "(* (eval ?a ?n) (eval ?b ?n))"),
+    ]
+}
+
+/// Rules that only hold for some operands, guarded by facts of the e-class analysis
+fn conditional_rules() -> Vec<Rewrite> {
+    vec![
+        rewrite!("mul by power of two"; "(* ?a ?c)" => {
+            MulToShl { a: var("?a"), c: var("?c") }
+        } if is_power_of_two(var("?c"))),
+        rewrite!("eq mul odd cancel"; "(== (* ?a ?c) (* ?b ?c))" => "(== ?a ?b)"
+                 if is_odd(var("?c"))),
+        rewrite!("neq mul odd cancel"; "(!= (* ?a ?c) (* ?b ?c))" => "(!= ?a ?b)"
+                 if is_odd(var("?c"))),
+        rewrite!("mul odd zero"; "(== (* ?a ?c) 0_i64)" => "(== ?a 0_i64)"
+                 if is_odd(var("?c"))),
+        rewrite!("nonzero eq zero"; "(== ?a 0_i64)" => "false" if is_nonzero(var("?a"))),
+        rewrite!("nonzero neq zero"; "(!= ?a 0_i64)" => "true" if is_nonzero(var("?a"))),
+    ]
+}
+
+/// Rewrites `(* a c)` to `(<< a k)` where `c` is the constant `2^k`
+#[derive(Debug)]
+struct MulToShl {
+    a: Var,
+    c: Var,
+}
+
+impl Applier<Lang, LangAnalysis> for MulToShl {
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph,
+        eclass: Id,
+        subst: &Subst,
+        _searcher_ast: Option<&PatternAst<Lang>>,
+        _rule_name: Symbol,
+    ) -> Vec<Id> {
+        let Some(c) = egraph[subst[self.c]].data.constant.and_then(Constant::as_i64) else {
+            return vec![];
+        };
+        let shift = egraph.add(Lang::I64(i64::from(c.trailing_zeros())));
+        let shl = egraph.add(Lang::Shl([subst[self.a], shift]));
+        if egraph.union(eclass, shl) {
+            vec![shl]
+        } else {
+            vec![]
+        }
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        vec![self.a, self.c]
+    }
+}
+
+fn allocation_rules() -> Vec<Rewrite> {
     vec![
         rewrite!("drop store"; "(load (store ?v ?s ?p) ?p)" => "?v"),
+        rewrite!("load past store"; "(load (store ?v ?s ?p) ?q)" => "(load ?s ?q)"
+                 if distinct_pointers(var("?p"), var("?q"))),
+        rewrite!("overwritten store"; "(store ?v2 (store ?v1 ?s ?p) ?p)" => "(store ?v2 ?s ?p)"),
+        rewrite!("store loaded value"; "(store (load ?s ?p) ?s ?p)" => "?s"),
+        rewrite!("commute stores";
+                 "(store ?v2 (store ?v1 ?s ?p) ?q)" => "(store ?v1 (store ?v2 ?s ?q) ?p)"
+                 if distinct_pointers(var("?p"), var("?q"))),
         rewrite!("lower load over phi"; "(load (phi ?c ?t ?e) ?p)" => "(phi ?c (load ?t ?p) (load ?e ?p))"),
     ]
 }
 
-fn phi_rules() -> Vec<Rewrite<Lang, ()>> {
+fn phi_rules() -> Vec<Rewrite> {
     let mut uni = vec![
         rewrite!("phi if true"; "(phi true ?t ?e)" => "?t"),
         rewrite!("phi if false"; "(phi false ?t ?e)" => "?e"),
         rewrite!("phi if same"; "(phi ?c ?t ?t)" => "?t"),
-        rewrite!("phi if neg"; "(phi ?c ?t ?e)" => "(phi (! ?c) ?e ?t)"),
     ];
     let bi = vec![
         rewrite!("not if"; "(phi ?c ?t ?e)" <=> "(phi (! ?c) ?e ?t)"),
@@ -58,7 +242,74 @@ fn phi_rules() -> Vec<Rewrite<Lang, ()>> {
     uni
 }
 
-fn cond_rules() -> Vec<Rewrite<Lang, ()>> {
+/// Binary operators and the names of their rules
+const BINARY_OPS: &[(&str, &str)] = &[
+    ("+", "addition"),
+    ("-", "subtraction"),
+    ("*", "multiplication"),
+    ("<<", "shift"),
+    ("&", "bitwise and"),
+    ("|", "bitwise or"),
+    ("&&", "and"),
+    ("||", "or"),
+    ("==", "eq"),
+    ("!=", "neq"),
+    ("<", "lt"),
+    (">", "gt"),
+    ("<=", "lte"),
+    (">=", "gte"),
+];
+
+/// Distributes binary operators over phi nodes and factors them back out. Distributing would
+/// copy the other operand into both branches of every phi it meets and blow up the e-graph, so
+/// it is limited to constant operands, which the analysis then folds into the branches.
+fn phi_distribution_rules() -> Vec<Rewrite> {
+    let pattern = |src: String| -> Pattern<Lang> { src.parse().unwrap() };
+    let mut rules = vec![];
+    for (op, name) in BINARY_OPS {
+        rules.push(
+            Rewrite::new(
+                format!("if {}", name),
+                pattern(format!("({} (phi ?c ?t ?e) ?x)", op)),
+                ConditionalApplier {
+                    condition: is_const(var("?x")),
+                    applier: pattern(format!("(phi ?c ({op} ?t ?x) ({op} ?e ?x))", op = op)),
+                },
+            )
+            .unwrap(),
+        );
+        rules.push(
+            Rewrite::new(
+                format!("if {} rhs", name),
+                pattern(format!("({} ?x (phi ?c ?t ?e))", op)),
+                ConditionalApplier {
+                    condition: is_const(var("?x")),
+                    applier: pattern(format!("(phi ?c ({op} ?x ?t) ({op} ?x ?e))", op = op)),
+                },
+            )
+            .unwrap(),
+        );
+        rules.push(
+            Rewrite::new(
+                format!("factor if {}", name),
+                pattern(format!("(phi ?c ({op} ?t ?x) ({op} ?e ?x))", op = op)),
+                pattern(format!("({} (phi ?c ?t ?e) ?x)", op)),
+            )
+            .unwrap(),
+        );
+        rules.push(
+            Rewrite::new(
+                format!("factor if {} rhs", name),
+                pattern(format!("(phi ?c ({op} ?x ?t) ({op} ?x ?e))", op = op)),
+                pattern(format!("({} ?x (phi ?c ?t ?e))", op)),
+            )
+            .unwrap(),
+        );
+    }
+    rules
+}
+
+fn cond_rules() -> Vec<Rewrite> {
     let uni = vec![
         rewrite!("not eq"; "(! (== ?a ?b))" => "(!= ?a ?b)"),
         rewrite!("not neq"; "(! (!= ?a ?b))" => "(== ?a ?b)"),
@@ -66,8 +317,8 @@ fn cond_rules() -> Vec<Rewrite<Lang, ()>> {
         rewrite!("not gt"; "(! (> ?a ?b))" => "(<= ?a ?b)"),
         rewrite!("lt or gt"; "(|| (< ?a ?b) (> ?a ?b))" => "(!= ?a ?b)"),
         rewrite!("lte and gte"; "(&& (<= ?a ?b) (>= ?a ?b))" => "(== ?a ?b)"),
-        rewrite!("lt and eq"; "(&& (< ?a ?b) (== ?a ?b))" => "(<= ?a ?b)"),
-        rewrite!("gt and eq"; "(&& (> ?a ?b) (== ?a ?b))" => "(>= ?a ?b)"),
+        rewrite!("lt or eq"; "(|| (< ?a ?b) (== ?a ?b))" => "(<= ?a ?b)"),
+        rewrite!("gt or eq"; "(|| (> ?a ?b) (== ?a ?b))" => "(>= ?a ?b)"),
         rewrite!("lte and neq"; "(&& (<= ?a ?b) (!= ?a ?b))" => "(< ?a ?b)"),
         rewrite!("gte and neq"; "(&& (>= ?a ?b) (!= ?a ?b))" => "(> ?a ?b)"),
         rewrite!("comm eq"; "(== ?a ?b)" => "(== ?b ?a)"),
@@ -86,7 +337,7 @@ fn cond_rules() -> Vec<Rewrite<Lang, ()>> {
     uni
 }
 
-fn logic_rules() -> Vec<Rewrite<Lang, ()>> {
+fn logic_rules() -> Vec<Rewrite> {
     let mut uni = vec![
         rewrite!("not true"; "(! true)" => "false"),
         rewrite!("not false"; "(! false)" => "true"),
@@ -108,3 +359,200 @@ fn logic_rules() -> Vec<Rewrite<Lang, ()>> {
     uni.extend(bi);
     uni
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{Limits, Optimizer, Phase};
+
+    fn saturate(egraph: EGraph, rules: Vec<Rewrite>, limits: Limits) -> EGraph {
+        Optimizer::default()
+            .with_phase(Phase::new("test", rules).with_limits(limits))
+            .run(egraph, vec![])
+            .egraph
+    }
+
+    fn names(rules: &[Rewrite]) -> Vec<String> {
+        rules.iter().map(|r| r.name.to_string()).collect()
+    }
+
+    #[test]
+    fn select_all() {
+        let rules = RuleSelection::default().select().unwrap();
+        assert_eq!(names(&rules), names(&rw_rules()));
+    }
+
+    #[test]
+    fn select_groups_and_rules() {
+        let rules = RuleSelection::default()
+            .include_group("logic")
+            .exclude_rule("comm and")
+            .include_rule("drop store")
+            .select()
+            .unwrap();
+        let names = names(&rules);
+        assert!(names.contains(&"drop store".to_string()));
+        assert!(names.contains(&"comm or".to_string()));
+        assert!(!names.contains(&"comm and".to_string()));
+        assert!(!names.contains(&"commutative addition".to_string()));
+    }
+
+    #[test]
+    fn conditional() {
+        let mut egraph = EGraph::default();
+        let mul = egraph.add_expr(&"(* x_v 8_i64)".parse().unwrap());
+        let cmp = egraph.add_expr(&"(== (* x_v 3_i64) (* y_v 3_i64))".parse().unwrap());
+        let even = egraph.add_expr(&"(== (* x_v 2_i64) (* y_v 2_i64))".parse().unwrap());
+        let egraph = &saturate(egraph, conditional_rules(), Limits::default());
+
+        let shl = egraph.lookup_expr(&"(<< x_v 3_i64)".parse().unwrap()).unwrap();
+        assert_eq!(egraph.find(mul), egraph.find(shl));
+        let cancelled = egraph.lookup_expr(&"(== x_v y_v)".parse().unwrap()).unwrap();
+        assert_eq!(egraph.find(cmp), egraph.find(cancelled));
+        assert_ne!(egraph.find(even), egraph.find(cancelled));
+    }
+
+    #[test]
+    fn load_past_store() {
+        let mut egraph = EGraph::default();
+        let (w0, w1) = (egraph.add(Lang::Alloca(0)), egraph.add(Lang::Alloca(1)));
+        let (p0, p1) = (egraph.add(Lang::Ptr(w0)), egraph.add(Lang::Ptr(w1)));
+        let (one, two) = (egraph.add(Lang::I64(1)), egraph.add(Lang::I64(2)));
+        let s1 = egraph.add(Lang::Store([one, w1, p1]));
+        let s0 = egraph.add(Lang::Store([two, s1, p0]));
+        let load1 = egraph.add(Lang::Load([s0, p1]));
+        let load0 = egraph.add(Lang::Load([s0, p0]));
+        let egraph = saturate(egraph, allocation_rules(), Limits::default());
+
+        assert_eq!(egraph.find(load1), egraph.find(one));
+        assert_eq!(egraph.find(load0), egraph.find(two));
+    }
+
+    #[test]
+    fn dead_stores() {
+        let mut egraph = EGraph::default();
+        let (w0, w1) = (egraph.add(Lang::Alloca(0)), egraph.add(Lang::Alloca(1)));
+        let (p0, p1) = (egraph.add(Lang::Ptr(w0)), egraph.add(Lang::Ptr(w1)));
+        let (one, two) = (egraph.add(Lang::I64(1)), egraph.add(Lang::I64(2)));
+        // The first store to p0 is overwritten after an unrelated store to p1
+        let first = egraph.add(Lang::Store([one, w0, p0]));
+        let other = egraph.add(Lang::Store([one, first, p1]));
+        let second = egraph.add(Lang::Store([two, other, p0]));
+        let loaded = egraph.add(Lang::Load([w1, p1]));
+        let reloaded = egraph.add(Lang::Store([loaded, w1, p1]));
+        let egraph = &saturate(egraph, allocation_rules(), Limits::default());
+
+        let live = egraph.lookup(Lang::Store([two, w0, p0])).unwrap();
+        let expected = egraph.lookup(Lang::Store([one, live, p1])).unwrap();
+        assert_eq!(egraph.find(second), egraph.find(expected));
+        assert_eq!(egraph.find(reloaded), egraph.find(w1));
+    }
+
+    /// Whether the expressions end up in the same class under the allocation rules, without
+    /// the excluded one
+    fn merged_without(left: &str, right: &str, excluded: Option<&str>) -> bool {
+        let rules = allocation_rules()
+            .into_iter()
+            .filter(|rule| Some(rule.name.as_str()) != excluded)
+            .collect();
+        let mut egraph = EGraph::default();
+        let left = egraph.add_expr(&left.parse().unwrap());
+        let right = egraph.add_expr(&right.parse().unwrap());
+        let egraph = saturate(egraph, rules, Limits::default());
+        egraph.find(left) == egraph.find(right)
+    }
+
+    #[test]
+    fn dead_store_rules() {
+        let overwritten = (
+            "(store 2_i64 (store 1_i64 alloca_0 (ptr alloca_0)) (ptr alloca_0))",
+            "(store 2_i64 alloca_0 (ptr alloca_0))",
+        );
+        let reloaded = (
+            "(store (load alloca_0 (ptr alloca_0)) alloca_0 (ptr alloca_0))",
+            "alloca_0",
+        );
+        // The dead store to p0 is only overwritten after the stores are commuted
+        let interleaved = (
+            "(store 2_i64 (store 3_i64 (store 1_i64 alloca_0 (ptr alloca_0)) (ptr alloca_1)) \
+             (ptr alloca_0))",
+            "(store 3_i64 (store 2_i64 alloca_0 (ptr alloca_0)) (ptr alloca_1))",
+        );
+        // Every case needs the rule it is paired with
+        let cases = [
+            (overwritten, "overwritten store"),
+            (reloaded, "store loaded value"),
+            (interleaved, "commute stores"),
+            (interleaved, "overwritten store"),
+        ];
+        for ((left, right), rule) in cases {
+            assert!(merged_without(left, right, None), "{}", left);
+            assert!(!merged_without(left, right, Some(rule)), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn hoisting() {
+        let mut egraph = EGraph::default();
+        let sum = egraph.add_expr(&"(theta (+ 0_i64 x_v) (+ y_v x_v))".parse().unwrap());
+        let eval = egraph.add_expr(&"(eval (* x_v 2_i64) z_v)".parse().unwrap());
+        let egraph = &saturate(egraph, loop_rules(), Limits::default());
+
+        let hoisted = egraph
+            .lookup_expr(&"(+ (theta 0_i64 y_v) x_v)".parse().unwrap())
+            .unwrap();
+        assert_eq!(egraph.find(sum), egraph.find(hoisted));
+        let invariant = egraph.lookup_expr(&"(* x_v 2_i64)".parse().unwrap()).unwrap();
+        assert_eq!(egraph.find(eval), egraph.find(invariant));
+    }
+
+    #[test]
+    fn phi_distribution() {
+        let mut egraph = EGraph::default();
+        let root = egraph.add_expr(&"(* (phi c_v (+ x_v 1_i64) 2_i64) 3_i64)".parse().unwrap());
+        let variable = egraph.add_expr(&"(* (phi c_v 1_i64 2_i64) y_v)".parse().unwrap());
+        let egraph = &saturate(egraph, phi_distribution_rules(), Limits::default());
+
+        let distributed = egraph
+            .lookup_expr(&"(phi c_v (* (+ x_v 1_i64) 3_i64) 6_i64)".parse().unwrap())
+            .unwrap();
+        assert_eq!(egraph.find(root), egraph.find(distributed));
+        assert_eq!(egraph[variable].nodes.len(), 1);
+    }
+
+    #[test]
+    fn reassociation() {
+        let mut egraph = EGraph::default();
+        let sum = egraph.add_expr(&"(+ (+ x_v 1_i64) 2_i64)".parse().unwrap());
+        let cancel = egraph.add_expr(&"(- (+ x_v (* 2_i64 y_v)) (* y_v 2_i64))".parse().unwrap());
+        let limits = Limits {
+            iters: 8,
+            ..Limits::default()
+        };
+        let egraph = &saturate(egraph, arithmetic_rules(), limits);
+
+        let x = egraph.lookup_expr(&"x_v".parse().unwrap()).unwrap();
+        let folded = egraph.lookup_expr(&"(+ x_v 3_i64)".parse().unwrap()).unwrap();
+        assert_eq!(egraph.find(sum), egraph.find(folded));
+        assert_eq!(egraph.find(cancel), egraph.find(x));
+    }
+
+    #[test]
+    fn bitwise() {
+        let mut egraph = EGraph::default();
+        let masked = egraph.add_expr(&"(& (| x_v 0_i64) (~ (~ x_v)))".parse().unwrap());
+        let cleared = egraph.add_expr(&"(<< (& x_v 0_i64) 0_i64)".parse().unwrap());
+        let egraph = &saturate(egraph, arithmetic_rules(), Limits::default());
+
+        let x = egraph.lookup_expr(&"x_v".parse().unwrap()).unwrap();
+        let zero = egraph.lookup_expr(&"0_i64".parse().unwrap()).unwrap();
+        assert_eq!(egraph.find(masked), egraph.find(x));
+        assert_eq!(egraph.find(cleared), egraph.find(zero));
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert!(RuleSelection::default().include_group("nope").select().is_err());
+        assert!(RuleSelection::default().exclude_rule("nope").select().is_err());
+    }
+}
@@ -0,0 +1,376 @@
+//! Offline rule synthesis in the style of Ruler: small terms are enumerated layer by layer,
+//! grouped by their values over sample environments, and every collision between a new term
+//! and an existing representative becomes a candidate rule. Candidates are then re-checked on
+//! a larger sample, verified with [`crate::verify`] and minimized against the rules accepted so
+//! far.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+use egg::{ENodeOrVar, FromOp, Id, Language, Pattern, RecExpr, Symbol, Var};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::interp::{Env, Expr, Store, Value};
+use crate::optimizer::{Limits, Optimizer, Phase};
+use crate::{verify, EGraph, Lang, Rewrite};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Ty {
+    Int,
+    Bool,
+}
+
+/// Operator, argument types and result type of every operator the synthesizer knows about
+const SIGNATURES: &[(&str, &[Ty], Ty)] = &[
+    ("+", &[Ty::Int, Ty::Int], Ty::Int),
+    ("-", &[Ty::Int, Ty::Int], Ty::Int),
+    ("*", &[Ty::Int, Ty::Int], Ty::Int),
+    ("<<", &[Ty::Int, Ty::Int], Ty::Int),
+    ("&", &[Ty::Int, Ty::Int], Ty::Int),
+    ("|", &[Ty::Int, Ty::Int], Ty::Int),
+    ("~", &[Ty::Int], Ty::Int),
+    ("==", &[Ty::Int, Ty::Int], Ty::Bool),
+    ("!=", &[Ty::Int, Ty::Int], Ty::Bool),
+    ("<", &[Ty::Int, Ty::Int], Ty::Bool),
+    (">", &[Ty::Int, Ty::Int], Ty::Bool),
+    ("<=", &[Ty::Int, Ty::Int], Ty::Bool),
+    (">=", &[Ty::Int, Ty::Int], Ty::Bool),
+    ("&&", &[Ty::Bool, Ty::Bool], Ty::Bool),
+    ("||", &[Ty::Bool, Ty::Bool], Ty::Bool),
+    ("!", &[Ty::Bool], Ty::Bool),
+    ("phi", &[Ty::Bool, Ty::Int, Ty::Int], Ty::Int),
+    ("phi", &[Ty::Bool, Ty::Bool, Ty::Bool], Ty::Bool),
+];
+
+pub struct SynthConfig {
+    /// Maximum depth of the enumerated terms
+    pub depth: usize,
+    pub int_vars: Vec<String>,
+    pub bool_vars: Vec<String>,
+    pub constants: Vec<i64>,
+    /// Operators to enumerate, in the `FromOp` syntax
+    pub ops: Vec<String>,
+    /// Number of environments used for fingerprinting
+    pub samples: usize,
+    pub seed: u64,
+}
+
+impl Default for SynthConfig {
+    fn default() -> Self {
+        Self {
+            depth: 2,
+            int_vars: vec!["a".into(), "b".into(), "c".into()],
+            bool_vars: vec!["p".into(), "q".into()],
+            constants: vec![-1, 0, 1],
+            ops: SIGNATURES
+                .iter()
+                .map(|(op, _, _)| op.to_string())
+                .filter(|op| op != "phi")
+                .collect(),
+            samples: 64,
+            seed: 0,
+        }
+    }
+}
+
+/// A synthesized equality, usable in both directions if `bidirectional`
+#[derive(Debug, Clone)]
+pub struct SynthesizedRule {
+    pub name: String,
+    pub lhs: Pattern<Lang>,
+    pub rhs: Pattern<Lang>,
+    pub bidirectional: bool,
+}
+
+impl SynthesizedRule {
+    pub fn rewrites(&self) -> Vec<Rewrite> {
+        let mut rewrites = vec![Rewrite::new(self.name.clone(), self.lhs.clone(), self.rhs.clone())
+            .expect("synthesized rule is well formed")];
+        if self.bidirectional {
+            rewrites.push(
+                Rewrite::new(format!("{}-rev", self.name), self.rhs.clone(), self.lhs.clone())
+                    .expect("synthesized rule is well formed"),
+            );
+        }
+        rewrites
+    }
+}
+
+impl Display for SynthesizedRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arrow = if self.bidirectional { "<=>" } else { "=>" };
+        write!(f, "{}: {} {} {}", self.name, self.lhs, arrow, self.rhs)
+    }
+}
+
+/// Renders the rules in the format read by [`crate::rule_file`]
+pub fn to_rule_file(rules: &[SynthesizedRule]) -> String {
+    let mut out = String::from("# Synthesized rules\n");
+    for rule in rules {
+        out.push_str(&rule.to_string());
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(Clone)]
+struct Term {
+    expr: RecExpr<Lang>,
+    ty: Ty,
+}
+
+impl Term {
+    fn vars(&self) -> BTreeSet<Symbol> {
+        self.expr
+            .as_ref()
+            .iter()
+            .filter_map(|node| match node {
+                Lang::Var(var) => Some(*var),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+type Fingerprint = Vec<Option<Value>>;
+
+pub fn synthesize(config: &SynthConfig) -> Vec<SynthesizedRule> {
+    let envs = sample_envs(config, config.samples, config.seed);
+    let check_envs = sample_envs(config, config.samples * 4, config.seed.wrapping_add(1));
+
+    let mut reprs: Vec<Term> = vec![];
+    let mut by_fingerprint: HashMap<Fingerprint, usize> = HashMap::new();
+    let mut candidates = vec![];
+
+    let mut prev_layer = 0..0;
+    for depth in 0..=config.depth {
+        let layer = if depth == 0 {
+            leaves(config)
+        } else {
+            grow(config, &reprs, prev_layer.clone())
+        };
+
+        let layer_start = reprs.len();
+        for term in layer {
+            let fp = fingerprint(&term.expr, &envs);
+            if fp.iter().all(Option::is_none) {
+                continue;
+            }
+            match by_fingerprint.get(&fp) {
+                Some(&repr) => candidates.push((term, repr)),
+                None => {
+                    by_fingerprint.insert(fp, reprs.len());
+                    reprs.push(term);
+                }
+            }
+        }
+        prev_layer = layer_start..reprs.len();
+    }
+
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .filter_map(|(term, repr)| candidate_rule(term, &reprs[repr]))
+        .filter(|(lhs, rhs, _)| fingerprint(lhs, &check_envs) == fingerprint(rhs, &check_envs))
+        .collect();
+    candidates.sort_by_key(|(lhs, rhs, _)| lhs.as_ref().len() + rhs.as_ref().len());
+
+    minimize(candidates)
+}
+
+/// Orients a collision so that the right-hand side does not introduce new variables. Rules
+/// are only bidirectional if neither side is a lone leaf, which would match every e-class.
+fn candidate_rule(term: Term, repr: &Term) -> Option<(RecExpr<Lang>, RecExpr<Lang>, bool)> {
+    let (term_vars, repr_vars) = (term.vars(), repr.vars());
+    if repr_vars.is_subset(&term_vars) {
+        let bidirectional = term_vars == repr_vars && repr.expr.as_ref().len() > 1;
+        Some((term.expr, repr.expr.clone(), bidirectional))
+    } else if term_vars.is_subset(&repr_vars) {
+        Some((repr.expr.clone(), term.expr, false))
+    } else {
+        None
+    }
+}
+
+/// Drops candidates that fail verification or that already follow from the accepted rules
+fn minimize(candidates: Vec<(RecExpr<Lang>, RecExpr<Lang>, bool)>) -> Vec<SynthesizedRule> {
+    let mut accepted: Vec<SynthesizedRule> = vec![];
+    let mut rewrites: Vec<Rewrite> = vec![];
+    for (lhs, rhs, bidirectional) in candidates {
+        if derivable(&rewrites, &lhs, &rhs) {
+            continue;
+        }
+        let rule = SynthesizedRule {
+            name: format!("synth {}", accepted.len()),
+            lhs: to_pattern(&lhs),
+            rhs: to_pattern(&rhs),
+            bidirectional,
+        };
+        let new_rewrites = rule.rewrites();
+        if !verify::verify_rules(&new_rewrites).is_empty() {
+            continue;
+        }
+        rewrites.extend(new_rewrites);
+        accepted.push(rule);
+    }
+    accepted
+}
+
+fn derivable(rules: &[Rewrite], lhs: &RecExpr<Lang>, rhs: &RecExpr<Lang>) -> bool {
+    if rules.is_empty() {
+        return false;
+    }
+    let mut egraph = EGraph::default();
+    let lhs = egraph.add_expr(lhs);
+    let rhs = egraph.add_expr(rhs);
+    let limits = Limits {
+        iters: 5,
+        nodes: 10_000,
+        ..Limits::default()
+    };
+    let saturated = Optimizer::default()
+        .with_phase(Phase::new("derivation", rules.to_vec()).with_limits(limits))
+        .run(egraph, vec![lhs, rhs]);
+    saturated.roots[0] == saturated.roots[1]
+}
+
+/// Turns the variables of a term into pattern variables
+fn to_pattern(expr: &RecExpr<Lang>) -> Pattern<Lang> {
+    let ast: Vec<_> = expr
+        .as_ref()
+        .iter()
+        .map(|node| match node {
+            Lang::Var(var) => ENodeOrVar::Var(
+                format!("?{}", var)
+                    .parse::<Var>()
+                    .expect("valid variable name"),
+            ),
+            node => ENodeOrVar::ENode(node.clone()),
+        })
+        .collect();
+    Pattern::new(ast.into())
+}
+
+fn leaves(config: &SynthConfig) -> Vec<Term> {
+    let leaf = |node: Lang, ty: Ty| Term {
+        expr: vec![node].into(),
+        ty,
+    };
+    let ints = config.int_vars.iter().map(|v| leaf(Lang::Var(v.as_str().into()), Ty::Int));
+    let bools = config.bool_vars.iter().map(|v| leaf(Lang::Var(v.as_str().into()), Ty::Bool));
+    let consts = config.constants.iter().map(|&i| leaf(Lang::I64(i), Ty::Int));
+    let truth = [true, false].map(|b| leaf(Lang::I1(b), Ty::Bool));
+    ints.chain(bools).chain(consts).chain(truth).collect()
+}
+
+/// Applies every operator to representatives, using at least one from the previous layer so
+/// that no term is enumerated twice
+fn grow(config: &SynthConfig, reprs: &[Term], prev_layer: std::ops::Range<usize>) -> Vec<Term> {
+    let mut terms = vec![];
+    for &(op, args, ty) in SIGNATURES {
+        if !config.ops.iter().any(|o| o == op) {
+            continue;
+        }
+        let choices: Vec<Vec<usize>> = args
+            .iter()
+            .map(|arg| (0..reprs.len()).filter(|&i| reprs[i].ty == *arg).collect())
+            .collect();
+        for combination in cartesian(&choices) {
+            if !combination.iter().any(|i| prev_layer.contains(i)) {
+                continue;
+            }
+            let children: Vec<_> = combination.iter().map(|&i| &reprs[i].expr).collect();
+            terms.push(Term {
+                expr: combine(op, &children),
+                ty,
+            });
+        }
+    }
+    terms
+}
+
+fn cartesian(choices: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    choices.iter().fold(vec![vec![]], |acc, choice| {
+        acc.into_iter()
+            .flat_map(|prefix| {
+                choice.iter().map(move |&c| {
+                    let mut combination = prefix.clone();
+                    combination.push(c);
+                    combination
+                })
+            })
+            .collect()
+    })
+}
+
+fn combine(op: &str, children: &[&RecExpr<Lang>]) -> RecExpr<Lang> {
+    let mut expr = RecExpr::default();
+    let ids = children
+        .iter()
+        .map(|child| {
+            let mut ids: Vec<Id> = vec![];
+            for node in child.as_ref() {
+                let node = node.clone().map_children(|c| ids[usize::from(c)]);
+                ids.push(expr.add(node));
+            }
+            *ids.last().unwrap()
+        })
+        .collect();
+    expr.add(Lang::from_op(op, ids).expect("known operator"));
+    expr
+}
+
+fn fingerprint(expr: &RecExpr<Lang>, envs: &[Env]) -> Fingerprint {
+    envs.iter()
+        .map(|env| Expr::new(expr).interp(env, &mut Store::default()).ok())
+        .collect()
+}
+
+/// Environments assigning edge-case and random values to the variables
+fn sample_envs(config: &SynthConfig, samples: usize, seed: u64) -> Vec<Env> {
+    const EDGES: [i64; 6] = [0, 1, -1, 2, i64::MAX, i64::MIN];
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..samples)
+        .map(|n| {
+            let mut env = Env::default();
+            for (i, var) in config.int_vars.iter().enumerate() {
+                let val = if n < EDGES.len() {
+                    EDGES[(n + i) % EDGES.len()]
+                } else if rng.gen_bool(0.5) {
+                    rng.gen_range(-16..=16)
+                } else {
+                    rng.gen()
+                };
+                env.set(var.as_str().into(), Value::I64(val));
+            }
+            for var in &config.bool_vars {
+                env.set(var.as_str().into(), Value::I1(rng.gen()));
+            }
+            env
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_commutativity() {
+        let config = SynthConfig {
+            depth: 1,
+            int_vars: vec!["a".into(), "b".into()],
+            bool_vars: vec![],
+            constants: vec![0],
+            ops: vec!["+".into()],
+            ..Default::default()
+        };
+        let rules = synthesize(&config);
+        let rules: Vec<_> = rules
+            .iter()
+            .map(|r| (r.lhs.to_string(), r.rhs.to_string()))
+            .collect();
+        assert!(rules.contains(&("(+ ?b ?a)".into(), "(+ ?a ?b)".into())));
+        assert!(rules.contains(&("(+ ?a 0_i64)".into(), "?a".into())));
+    }
+}
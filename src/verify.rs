@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use egg::{ENodeOrVar, Id, Language, PatternAst, RecExpr, Var};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::consistency::check_consistency;
+use crate::interp::{Env, Expr, Store, Value};
+use crate::{EGraph, Lang, Rewrite};
+
+/// Maximum number of instantiations tried per rule, larger spaces are sampled randomly
+const MAX_INSTANCES: usize = 4096;
+
+/// Loop iterations both sides are compared in
+const ITERATIONS: usize = 3;
+
+/// An instantiation of a rule under which its sides evaluate differently
+#[derive(Debug, Clone)]
+pub struct Counterexample {
+    pub rule: String,
+    pub lhs: RecExpr<Lang>,
+    pub rhs: RecExpr<Lang>,
+    pub lhs_val: Value,
+    pub rhs_val: Result<Value, String>,
+}
+
+impl Display for Counterexample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rule `{}`: {} = {:?}, but {} = {:?}",
+            self.rule, self.lhs, self.lhs_val, self.rhs, self.rhs_val
+        )
+    }
+}
+
+/// Small values pattern variables are instantiated with, including the loop-variant sequence
+/// 0, 1, 1, ... Every entry is a term in `RecExpr` node order.
+fn domain() -> Vec<Vec<Lang>> {
+    let mut values: Vec<_> = (-2..=3).map(|i| vec![Lang::I64(i)]).collect();
+    values.push(vec![
+        Lang::I64(0),
+        Lang::I64(1),
+        Lang::Theta([Id::from(0), Id::from(1)]),
+    ]);
+    values.push(vec![Lang::I1(true)]);
+    values.push(vec![Lang::I1(false)]);
+    for alloca in 0..2 {
+        values.push(vec![Lang::Alloca(alloca)]);
+        values.push(vec![Lang::Alloca(alloca), Lang::Ptr(Id::from(0))]);
+    }
+    values
+}
+
+/// Checks every rule, see [`verify_rule`]
+pub fn verify_rules(rules: &[Rewrite]) -> Vec<Counterexample> {
+    rules.iter().flat_map(verify_rule).collect()
+}
+
+/// Instantiates the pattern variables of the rule with small values, exhaustively if there are
+/// few enough combinations and randomly otherwise, and compares both sides using the
+/// interpreter in the first loop iterations. Instances whose left-hand side cannot be evaluated
+/// are skipped, as are instances evaluating to pointers or witnesses, since those depend on the
+/// shape of the term.
+/// Rules whose applier is not a pattern are applied to the instantiated left-hand side and the
+/// resulting e-graph is checked with [`check_consistency`].
+pub fn verify_rule(rule: &Rewrite) -> Vec<Counterexample> {
+    let Some(lhs) = rule.searcher.get_pattern_ast() else {
+        return vec![];
+    };
+    let rhs = rule.applier.get_pattern_ast();
+    let vars = rule.searcher.vars();
+    let domain = domain();
+    let env = Env::default();
+
+    let mut counterexamples = vec![];
+    for assignment in assignments(vars.len(), domain.len()) {
+        let subst: HashMap<Var, &[Lang]> = vars
+            .iter()
+            .zip(&assignment)
+            .map(|(&var, &val)| (var, domain[val].as_slice()))
+            .collect();
+        let lhs = instantiate(lhs, &subst);
+        let lhs_vals: Option<Vec<Value>> = (0..ITERATIONS)
+            .map(|iteration| match interp(&lhs, &env, iteration) {
+                Ok(val @ (Value::I64(_) | Value::I1(_))) => Some(val),
+                _ => None,
+            })
+            .collect();
+        let Some(lhs_vals) = lhs_vals else {
+            continue;
+        };
+        let Some(rhs) = rhs else {
+            counterexamples.extend(applied_counterexample(rule, &lhs, &env));
+            continue;
+        };
+        let rhs = instantiate(rhs, &subst);
+
+        let differing = lhs_vals
+            .into_iter()
+            .enumerate()
+            .map(|(iteration, lhs_val)| (lhs_val, interp(&rhs, &env, iteration)))
+            .find(|(lhs_val, rhs_val)| *rhs_val != Ok(*lhs_val));
+        let Some((lhs_val, rhs_val)) = differing else {
+            continue;
+        };
+        if !fires(rule, &lhs, &rhs) {
+            continue;
+        }
+        counterexamples.push(Counterexample {
+            rule: rule.name.to_string(),
+            lhs,
+            rhs,
+            lhs_val,
+            rhs_val,
+        });
+    }
+    counterexamples
+}
+
+fn interp(expr: &RecExpr<Lang>, env: &Env, iteration: usize) -> Result<Value, String> {
+    Expr::new(expr)
+        .at_iteration(iteration)
+        .interp(env, &mut Store::default())
+}
+
+/// Indices into the domain for each variable
+pub(crate) fn assignments(vars: usize, domain: usize) -> Vec<Vec<usize>> {
+    let total = u32::try_from(vars)
+        .ok()
+        .and_then(|vars| domain.checked_pow(vars))
+        .filter(|&total| total <= MAX_INSTANCES);
+
+    match total {
+        Some(total) => (0..total)
+            .map(|mut n| {
+                (0..vars)
+                    .map(|_| {
+                        let val = n % domain;
+                        n /= domain;
+                        val
+                    })
+                    .collect()
+            })
+            .collect(),
+        None => {
+            let mut rng = StdRng::seed_from_u64(0);
+            (0..MAX_INSTANCES)
+                .map(|_| (0..vars).map(|_| rng.gen_range(0..domain)).collect())
+                .collect()
+        }
+    }
+}
+
+/// Builds the term described by the pattern, sharing identical subterms so that pointers and
+/// witnesses keep their identity in the interpreter
+fn instantiate(pattern: &PatternAst<Lang>, subst: &HashMap<Var, &[Lang]>) -> RecExpr<Lang> {
+    let mut expr = RecExpr::default();
+    let mut memo = HashMap::new();
+    let mut add = |expr: &mut RecExpr<Lang>, node: Lang| {
+        *memo
+            .entry(node.clone())
+            .or_insert_with(|| expr.add(node))
+    };
+
+    let mut ids: Vec<Id> = vec![];
+    for node in pattern.as_ref() {
+        let id = match node {
+            ENodeOrVar::ENode(node) => {
+                let node = node.clone().map_children(|child| ids[usize::from(child)]);
+                add(&mut expr, node)
+            }
+            ENodeOrVar::Var(var) => {
+                let mut term_ids: Vec<Id> = vec![];
+                for node in subst[var] {
+                    let node = node.clone().map_children(|child| term_ids[usize::from(child)]);
+                    term_ids.push(add(&mut expr, node));
+                }
+                *term_ids.last().unwrap()
+            }
+        };
+        ids.push(id);
+    }
+    expr
+}
+
+/// Whether applying the rule to `lhs` really makes it equal to `rhs`, which might not be the
+/// case for conditional rules
+fn fires(rule: &Rewrite, lhs: &RecExpr<Lang>, rhs: &RecExpr<Lang>) -> bool {
+    let mut egraph = EGraph::default();
+    let root = egraph.add_expr(lhs);
+    egraph.rebuild();
+    let matches = rule.search(&egraph);
+    rule.apply(&mut egraph, &matches);
+    egraph.rebuild();
+    egraph
+        .lookup_expr(rhs)
+        .map_or(false, |id| egraph.find(id) == egraph.find(root))
+}
+
+/// Applies the rule to `lhs` and reports the first e-class whose members evaluate differently
+fn applied_counterexample(
+    rule: &Rewrite,
+    lhs: &RecExpr<Lang>,
+    env: &Env,
+) -> Option<Counterexample> {
+    let mut egraph = EGraph::default();
+    egraph.add_expr(lhs);
+    egraph.rebuild();
+    let matches = rule.search(&egraph);
+    rule.apply(&mut egraph, &matches);
+    egraph.rebuild();
+    check_consistency(&mut egraph, std::slice::from_ref(env))
+        .into_iter()
+        .next()
+        .map(|inconsistency| Counterexample {
+            rule: rule.name.to_string(),
+            lhs: inconsistency.expected.0,
+            rhs: inconsistency.found.0,
+            lhs_val: inconsistency.expected.1,
+            rhs_val: Ok(inconsistency.found.1),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egg::rewrite;
+
+    #[test]
+    fn sound_rule() {
+        let rule = rewrite!("zero addition"; "(+ 0_i64 ?a)" => "?a");
+        assert!(verify_rule(&rule).is_empty());
+    }
+
+    #[test]
+    fn unsound_rule() {
+        let rule = rewrite!("bad sub"; "(- ?a ?b)" => "(- ?b ?a)");
+        let counterexamples = verify_rule(&rule);
+        assert!(!counterexamples.is_empty());
+        assert!(counterexamples.iter().all(|c| c.rule == "bad sub"));
+    }
+
+    #[test]
+    fn custom_applier() {
+        let rules = crate::rules::rule_groups()
+            .into_iter()
+            .find(|(name, _)| *name == "conditional")
+            .unwrap()
+            .1;
+        assert!(verify_rules(&rules).is_empty());
+    }
+
+    #[test]
+    fn loop_rules() {
+        let hoist = rewrite!("hoist"; "(theta (+ ?i ?a) (+ ?n ?a))" => "(+ (theta ?i ?n) ?a)");
+        assert!(!verify_rule(&hoist).is_empty());
+        let rules = crate::rules::rule_groups()
+            .into_iter()
+            .find(|(name, _)| *name == "loop")
+            .unwrap()
+            .1;
+        assert!(verify_rules(&rules).is_empty());
+    }
+
+    #[test]
+    fn memory_rule() {
+        let rule = rewrite!("drop store"; "(load (store ?v ?s ?p) ?p)" => "?v");
+        assert!(verify_rule(&rule).is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+use ::lang::conv::to_epeg;
+use ::lang::equiv::{check_equivalence, Verdict};
+use ::lang::optimizer::{Limits, Optimizer};
+use lang::Function;
+use llvm_ir::Module;
+
+fn load(path: &str, name: &str) -> Function {
+    let module = Module::from_bc_path(path).unwrap();
+    to_epeg::parse_function(module.get_func_by_name(name).unwrap())
+}
+
+#[test]
+fn stack_allocated_identity() {
+    let with_alloca = load("llvm_programs/identity/identity.bc", "identity");
+    let without = load("llvm_programs/identity/identity_noalloc.bc", "identity");
+    let optimizer = Optimizer::default().with_limits(Limits {
+        iters: 30,
+        ..Limits::default()
+    });
+    let verdict = check_equivalence(&with_alloca, &without, &optimizer).unwrap();
+    assert!(matches!(verdict, Verdict::Equivalent { .. }), "{}", verdict);
+}
+
+#[test]
+fn different_programs() {
+    let if_expr = load("llvm_programs/if/if.bc", "if_expr");
+    let complex_ifs = load("llvm_programs/complex_ifs/complex_ifs.bc", "complex_ifs");
+    let verdict = check_equivalence(&if_expr, &complex_ifs, &Optimizer::default()).unwrap();
+    let Verdict::Counterexample { args, left, right } = verdict else {
+        panic!("expected a counterexample, found {}", verdict);
+    };
+    assert_eq!(args.len(), 3);
+    assert_ne!(left, right);
+}
+
+#[test]
+fn different_arities() {
+    let identity = load("llvm_programs/identity/identity.bc", "identity");
+    let add = load("llvm_programs/add/add.bc", "add");
+    assert!(check_equivalence(&identity, &add, &Optimizer::default()).is_err());
+}
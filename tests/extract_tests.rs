@@ -0,0 +1,86 @@
+use ::lang::conv::to_epeg;
+use ::lang::cost_fn::{node_cost, NoAlloc};
+use ::lang::extract::dag::DagExtractor;
+use ::lang::extract::dag_cost;
+use ::lang::interp;
+use ::lang::optimizer::Optimizer;
+use egg::{Extractor, RecExpr};
+use lang::{EGraph, Function, Lang};
+use llvm_ir::Module;
+
+/// Saturates the function and checks that the tree and DAG extractors both produce a program
+/// computing `expected`. The greedy DAG extractor is a heuristic that can lose to the tree
+/// extractor, so their costs with shared subterms counted once are only reported.
+fn compare(path: &str, name: &str, args: &[i64], expected: i64) {
+    let module = Module::from_bc_path(path).unwrap();
+    let func = module.get_func_by_name(name).unwrap();
+    let Function { body, root, .. } = to_epeg::parse_function(func);
+    let mut env = interp::Env::default();
+    for (i, &arg) in args.iter().enumerate() {
+        env.set(i.to_string().into(), interp::Value::I64(arg));
+    }
+
+    let saturated = Optimizer::default().run_rooted(&body, root);
+    let (_, tree) = Extractor::new(&saturated.egraph, NoAlloc).find_best(saturated.roots[0]);
+    let (cost, dag) = DagExtractor::new(&saturated.egraph, node_cost)
+        .find_best(saturated.roots[0])
+        .unwrap();
+    let tree_cost = dag_cost(&tree, node_cost);
+    println!("{}: DAG cost {}, tree cost {}", name, cost, tree_cost);
+
+    for best in [&tree, &dag] {
+        let res = interp::Expr::new(best).interp(&env, &mut interp::Store::default());
+        assert_eq!(res, Ok(interp::Value::I64(expected)), "{}: {}", name, best);
+    }
+}
+
+#[test]
+fn add() {
+    compare("llvm_programs/add/add.bc", "add", &[3, 4], 7);
+}
+
+#[test]
+fn if_expr() {
+    compare("llvm_programs/if/if.bc", "if_expr", &[3, 4, 1], 3);
+}
+
+#[test]
+fn complex_ifs() {
+    compare(
+        "llvm_programs/complex_ifs/complex_ifs.bc",
+        "complex_ifs",
+        &[5, 4, 1],
+        -10,
+    );
+}
+
+#[test]
+fn identity() {
+    compare("llvm_programs/identity/identity.bc", "identity", &[2], 2);
+}
+
+/// The tree extractor counts the expensive condition once per phi and prefers the long product,
+/// which costs more than the phis once the condition is shared
+#[test]
+fn shared_condition() {
+    let cond = "(< (* x_v x_v) (* y_v y_v))";
+    let phis: RecExpr<Lang> = format!("(+ (phi {0} a_v b_v) (phi {0} b_v a_v))", cond)
+        .parse()
+        .unwrap();
+    let product = (0..10).fold("v10_v".to_string(), |product, i| {
+        format!("(* v{}_v {})", i, product)
+    });
+    let mut egraph = EGraph::default();
+    let root = egraph.add_expr(&phis);
+    let other = egraph.add_expr(&product.parse().unwrap());
+    egraph.union(root, other);
+    egraph.rebuild();
+
+    let (_, tree) = Extractor::new(&egraph, NoAlloc).find_best(root);
+    let (cost, dag) = DagExtractor::new(&egraph, node_cost)
+        .find_best(root)
+        .unwrap();
+    assert_eq!(tree.to_string(), product);
+    assert_eq!(dag.to_string(), phis.to_string());
+    assert!(cost < dag_cost(&tree, node_cost));
+}
@@ -0,0 +1,16 @@
+use ::lang::conv::to_epeg;
+use lang::Function;
+use llvm_ir::Module;
+use rstest::rstest;
+
+#[rstest]
+#[case("llvm_programs/add/add.bc", "add")]
+#[case("llvm_programs/if/if.bc", "if_expr")]
+#[case("llvm_programs/complex_ifs/complex_ifs.bc", "complex_ifs")]
+#[case("llvm_programs/identity/identity.bc", "identity")]
+fn converted_function_round_trip(#[case] path: &str, #[case] name: &str) {
+    let module = Module::from_bc_path(path).unwrap();
+    let function = to_epeg::parse_function(module.get_func_by_name(name).unwrap());
+    let printed = function.to_string();
+    assert_eq!(printed.parse::<Function>(), Ok(function), "{}", printed);
+}
@@ -0,0 +1,132 @@
+use ::lang::{Function, Lang};
+use egg::{FromOp, Id, Language, RecExpr};
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+const UNARY: &[&str] = &["~", "!", "pass", "ptr"];
+const BINARY: &[&str] = &[
+    "+", "-", "*", "<<", "&", "|", "&&", "||", "==", "!=", "<", ">", "<=", ">=", "theta", "eval",
+    "load",
+];
+const TERNARY: &[&str] = &["phi", "store"];
+
+fn leaf() -> impl Strategy<Value = Lang> {
+    prop_oneof![
+        any::<bool>().prop_map(Lang::I1),
+        any::<i64>().prop_map(Lang::I64),
+        any::<u64>().prop_map(Lang::Alloca),
+        "[a-z][a-z0-9_]{0,8}".prop_map(|name| Lang::Var(name.as_str().into())),
+    ]
+}
+
+/// Operator with placeholder children
+fn operator() -> impl Strategy<Value = Lang> {
+    let with_arity = |ops: &'static [&'static str], arity: usize| {
+        prop::sample::select(ops)
+            .prop_map(move |op| Lang::from_op(op, vec![Id::from(0); arity]).unwrap())
+    };
+    prop_oneof![
+        with_arity(UNARY, 1),
+        with_arity(BINARY, 2),
+        with_arity(TERNARY, 3)
+    ]
+}
+
+#[derive(Debug, Clone)]
+struct Tree(Lang, Vec<Tree>);
+
+fn tree() -> impl Strategy<Value = Tree> {
+    leaf()
+        .prop_map(|leaf| Tree(leaf, vec![]))
+        .prop_recursive(5, 64, 3, |inner| {
+            (operator(), prop::collection::vec(inner, 3)).prop_map(|(node, mut children)| {
+                children.truncate(node.len());
+                Tree(node, children)
+            })
+        })
+}
+
+/// Adds the nodes in post-order, like parsing does
+fn add_tree(tree: &Tree, expr: &mut RecExpr<Lang>) -> Id {
+    let mut children = tree
+        .1
+        .iter()
+        .map(|child| add_tree(child, expr))
+        .collect::<Vec<_>>();
+    let node = tree.0.clone().map_children(|_| children.remove(0));
+    expr.add(node)
+}
+
+/// Every node refers to earlier ones, so nodes can be shared or unreachable from the root
+fn dag() -> impl Strategy<Value = (RecExpr<Lang>, Index)> {
+    let node = prop_oneof![
+        leaf().prop_map(|leaf| (leaf, Vec::<Index>::new())),
+        (operator(), prop::collection::vec(any::<Index>(), 3)),
+    ];
+    (leaf(), prop::collection::vec(node, 0..32), any::<Index>()).prop_map(|(first, nodes, root)| {
+        let mut expr = RecExpr::default();
+        expr.add(first);
+        for (node, children) in nodes {
+            let len = expr.as_ref().len();
+            let mut children = children.into_iter();
+            let node = node.map_children(|_| Id::from(children.next().unwrap().index(len)));
+            expr.add(node);
+        }
+        (expr, root)
+    })
+}
+
+proptest! {
+    #[test]
+    fn printed_tree_parses_back(tree in tree()) {
+        let mut expr = RecExpr::default();
+        add_tree(&tree, &mut expr);
+        let printed = expr.to_string();
+        prop_assert_eq!(printed.parse::<RecExpr<Lang>>().unwrap(), expr, "{}", printed);
+    }
+
+    #[test]
+    fn printed_function_parses_back((expr, root) in dag()) {
+        // Unlike s-expressions, function files keep shared and unreachable nodes
+        let function = Function {
+            name: "f".to_string(),
+            params: vec![],
+            root: Id::from(root.index(expr.as_ref().len())),
+            body: expr,
+        };
+        let printed = function.to_string();
+        prop_assert_eq!(printed.parse::<Function>(), Ok(function), "{}", printed);
+    }
+}
+
+#[test]
+fn allocations_keep_their_number() {
+    let expr: RecExpr<Lang> = "(load alloca_7 (ptr alloca_7))".parse().unwrap();
+    assert_eq!(expr.as_ref()[0], Lang::Alloca(7));
+    assert_eq!(expr.to_string(), "(load alloca_7 (ptr alloca_7))");
+    assert_eq!(
+        "(load alloca_7 (ptr alloca_7))"
+            .parse::<RecExpr<Lang>>()
+            .unwrap(),
+        expr
+    );
+    assert!("alloca".parse::<RecExpr<Lang>>().is_err());
+}
+
+#[test]
+fn underscores_in_names() {
+    let expr: RecExpr<Lang> = "(+ my_var_v -3_i64)".parse().unwrap();
+    assert_eq!(expr.as_ref()[0], Lang::Var("my_var".into()));
+    assert_eq!(expr.as_ref()[1], Lang::I64(-3));
+    assert!("x_i64".parse::<RecExpr<Lang>>().is_err());
+}
+
+#[test]
+fn operator_errors() {
+    let error = |op, arity| Lang::from_op(op, vec![Id::from(0); arity]).unwrap_err();
+    assert_eq!(error("foo", 2), "Unknown operator: foo");
+    assert_eq!(error("x_u8", 0), "Unknown operator: x_u8");
+    assert_eq!(error("+", 1), "`+` takes 2 operands, found 1");
+    assert_eq!(error("true", 1), "`true` takes 0 operands, found 1");
+    assert_eq!(error("x_v", 1), "`x_v` takes 0 operands, found 1");
+}
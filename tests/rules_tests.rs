@@ -0,0 +1,87 @@
+use ::lang::conv::to_epeg;
+use ::lang::cost_fn::NoAlloc;
+use ::lang::lang::Lang;
+use ::lang::optimizer::{Limits, Optimizer, Phase};
+use ::lang::rules::{rw_rules, RuleSelection};
+use ::lang::{verify, EGraph};
+use egg::Extractor;
+use llvm_ir::Module;
+
+#[test]
+fn all_rules_sound() {
+    let counterexamples = verify::verify_rules(&rw_rules());
+    let report: Vec<_> = counterexamples.iter().map(ToString::to_string).collect();
+    assert!(report.is_empty(), "unsound rules:\n{}", report.join("\n"));
+}
+
+/// Example from Tate et al., "Equality Saturation: A New Approach to Optimization"
+///
+/// ```c
+/// i := 0;
+/// while (...) {
+///   use(i * 5);
+///   i := i + 1;
+///   if (c) {
+///     i := i + 3;
+///   }
+/// }
+/// ```
+///
+/// `i * 5` is an induction variable starting at 0 and stepping by 5, or by 20 if `c` holds.
+#[test]
+fn ross_tate_example() {
+    let mut egraph = EGraph::default();
+    let zero = egraph.add(Lang::I64(0));
+    let one = egraph.add(Lang::I64(1));
+    let three = egraph.add(Lang::I64(3));
+    let five = egraph.add(Lang::I64(5));
+    let cond = egraph.add(Lang::Var("c".into()));
+    // Stands for the loop itself until it is unioned with the theta node
+    let i = egraph.add(Lang::Var("i".into()));
+    let next = egraph.add(Lang::Add([one, i]));
+    let next_if = egraph.add(Lang::Add([three, next]));
+    let phi = egraph.add(Lang::Phi([cond, next_if, next]));
+    let theta = egraph.add(Lang::Theta([zero, phi]));
+    let times = egraph.add(Lang::Mul([theta, five]));
+    egraph.union(i, theta);
+
+    let saturated = Optimizer::default()
+        .with_limits(Limits {
+            iters: 10,
+            ..Limits::default()
+        })
+        .run(egraph, vec![times]);
+    let egraph = &saturated.egraph;
+
+    let lookup = |node: Lang| {
+        egraph
+            .lookup(node.clone())
+            .unwrap_or_else(|| panic!("missing {:?}", node))
+    };
+    let fifteen = lookup(Lang::I64(15));
+    let next = lookup(Lang::Add([five, times]));
+    let next_if = lookup(Lang::Add([fifteen, next]));
+    let phi = lookup(Lang::Phi([cond, next_if, next]));
+    let reduced = lookup(Lang::Theta([zero, phi]));
+    assert_eq!(egraph.find(times), egraph.find(reduced));
+}
+
+/// `add` spills its parameters to two locals before loading them back. The first load reads
+/// the memory state after the store to the other local, so it needs "load past store".
+#[test]
+fn two_locals() {
+    let module = Module::from_bc_path("llvm_programs/add/add.bc").unwrap();
+    let function = to_epeg::parse_function(module.get_func_by_name("add").unwrap());
+    let optimized = |selection: RuleSelection| {
+        let phase = Phase::from_selection("allocation", &selection).unwrap();
+        let saturated = Optimizer::default()
+            .with_phase(phase)
+            .run_function(&function);
+        let extractor = Extractor::new(&saturated.egraph, NoAlloc);
+        extractor.find_best(saturated.roots[0]).1.to_string()
+    };
+    let allocation = RuleSelection::default().include_group("allocation");
+    assert_eq!(optimized(allocation.clone()), "(+ 0_v 1_v)");
+    let without = optimized(allocation.exclude_rule("load past store"));
+    assert!(without.contains("load"), "{}", without);
+}
@@ -1,7 +1,8 @@
 use ::lang::conv::to_epeg;
+use ::lang::cost_fn::NoAlloc;
 use ::lang::interp;
-use ::lang::rules::rw_rules;
-use egg::{AstDepth, Extractor, Runner};
+use ::lang::optimizer::{Limits, Optimizer};
+use egg::{AstDepth, Extractor};
 use lang::Function;
 use llvm_ir::Module;
 
@@ -24,15 +25,15 @@ fn add_conversion() {
     let res = expr.interp(&env, &mut interp::Store::default());
     assert_eq!(res, Ok(interp::Value::I64(7)));
 
-    let runner = Runner::default()
-        .with_node_limit(1000000)
-        .with_time_limit(std::time::Duration::from_secs(15))
-        .with_iter_limit(100)
-        .with_expr(&expr_og)
-        .run(&rw_rules());
+    let saturated = Optimizer::default()
+        .with_limits(Limits {
+            nodes: 1_000_000,
+            ..Limits::default()
+        })
+        .run_rooted(&expr_og, root);
 
-    let extractor = Extractor::new(&runner.egraph, AstDepth);
-    let (_, best) = extractor.find_best(root);
+    let extractor = Extractor::new(&saturated.egraph, AstDepth);
+    let (_, best) = extractor.find_best(saturated.roots[0]);
     println!("best:\n{}", best.pretty(40));
 
     let res = interp::Expr::new(&best).interp(&env, &mut interp::Store::default());
@@ -60,15 +61,15 @@ fn if_conversion() {
     let res = expr.interp(&env, &mut interp::Store::default());
     assert_eq!(res, Ok(interp::Value::I64(3)));
 
-    let runner = Runner::default()
-        .with_node_limit(1000000)
-        .with_time_limit(std::time::Duration::from_secs(15))
-        .with_iter_limit(100)
-        .with_expr(&expr_og)
-        .run(&rw_rules());
+    let saturated = Optimizer::default()
+        .with_limits(Limits {
+            nodes: 1_000_000,
+            ..Limits::default()
+        })
+        .run_rooted(&expr_og, root);
 
-    let extractor = Extractor::new(&runner.egraph, AstDepth);
-    let (_, best) = extractor.find_best(root);
+    let extractor = Extractor::new(&saturated.egraph, AstDepth);
+    let (_, best) = extractor.find_best(saturated.roots[0]);
     println!("best:\n{}", best.pretty(40));
 
     let res = interp::Expr::new(&best).interp(&env, &mut interp::Store::default());
@@ -96,18 +97,40 @@ fn complex_if_conversion() {
     let res = expr.interp(&env, &mut interp::Store::default());
     assert_eq!(res, Ok(interp::Value::I64(-10)));
 
-    let runner = Runner::default()
-        .with_node_limit(1000000)
-        .with_time_limit(std::time::Duration::from_secs(15))
-        .with_iter_limit(100)
-        .with_expr(&expr_og)
-        .run(&rw_rules());
+    let saturated = Optimizer::default()
+        .with_limits(Limits {
+            nodes: 1_000_000,
+            ..Limits::default()
+        })
+        .run_rooted(&expr_og, root);
 
-    let extractor = Extractor::new(&runner.egraph, AstDepth);
-    let (_, best) = extractor.find_best(runner.roots[0]);
+    let extractor = Extractor::new(&saturated.egraph, AstDepth);
+    let (_, best) = extractor.find_best(saturated.roots[0]);
     println!("best:\n{}", best.pretty(40));
 
     let res = interp::Expr::new(&best).interp(&env, &mut interp::Store::default());
 
     assert_eq!(res, Ok(interp::Value::I64(-10)));
 }
+
+#[test]
+fn identity_conversion() {
+    let module = Module::from_bc_path("llvm_programs/identity/identity.bc").unwrap();
+    let func = module.get_func_by_name("identity").unwrap();
+    let Function {
+        body: expr_og,
+        root,
+        ..
+    } = to_epeg::parse_function(func);
+
+    let saturated = Optimizer::default()
+        .with_limits(Limits {
+            iters: 30,
+            ..Limits::default()
+        })
+        .run_rooted(&expr_og, root);
+
+    let extractor = Extractor::new(&saturated.egraph, NoAlloc);
+    let (_, best) = extractor.find_best(saturated.roots[0]);
+    assert_eq!(best.to_string(), "0_v");
+}